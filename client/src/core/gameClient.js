@@ -49,6 +49,13 @@ export default class GameClient {
     this.network.on("state", (s) => {
       this.gameState = s;
 
+      // campaign rooms move on to the next stage's map after a win
+      const stageLevel = s && s.campaign ? s.campaign.level : null;
+      if (stageLevel && stageLevel !== this.campaignLevel) {
+        this.campaignLevel = stageLevel;
+        this.sceneManager.setLevelByKey(stageLevel);
+      }
+
       // If server says game is over, stop the game loop and show menu again.
       // (UI overlay may also show a Game Over panel; menu is the navigation target.)
       if (s && s.game_over === true) {
@@ -70,6 +77,15 @@ export default class GameClient {
       }
     });
 
+    // level paths for placing chain marbles (one per track, sent after welcome)
+    this.network.on("path", (p) => {
+      this.sceneManager.marbleRenderer.setPath(
+        p.points,
+        p.total_length,
+        p.track || 0,
+      );
+    });
+
     // create the main menu and show it initially
     this.menu = new MainMenu({
       parent: document.body,
@@ -36,6 +36,7 @@ export default class MainMenu {
     // If network client is provided, request room list
     if (this.networkClient) {
       this._setupNetworkHandlers();
+      this.networkClient.listLevels();
       this.networkClient.listRooms();
     }
   }
@@ -215,13 +216,7 @@ export default class MainMenu {
     this.levelSelect = document.createElement("select");
     this.levelSelect.id = "mc-level-select";
 
-    this.levels.forEach((lvl) => {
-      const opt = document.createElement("option");
-      opt.value = lvl;
-      opt.textContent = lvl;
-      if (lvl === this.selectedLevel) opt.selected = true;
-      this.levelSelect.appendChild(opt);
-    });
+    this._renderLevels();
 
     this.levelSelect.addEventListener("change", () => {
       this.selectedLevel = this.levelSelect.value;
@@ -262,6 +257,11 @@ export default class MainMenu {
       this.setRooms(rooms);
     });
 
+    // Handle level list from the server
+    this.networkClient.on("levels_list", (levels) => {
+      this.setLevels(levels);
+    });
+
     // Handle room created - auto-join it
     this.networkClient.on("room_created", (data) => {
       console.log("Room created:", data);
@@ -298,6 +298,32 @@ export default class MainMenu {
   }
 
   // setRooms([{id,name,players,maxPlayers}, ...])
+  // Replace the level choices with the server's `levels_list`
+  setLevels(levels) {
+    if (!Array.isArray(levels) || levels.length === 0) return;
+    this.levels = levels.map((l) => ({
+      id: l.id,
+      label: `${l.display_name} (${l.difficulty}, ${l.recommended_players}p)`,
+    }));
+    if (!this.levels.some((l) => l.id === this.selectedLevel)) {
+      this.selectedLevel = this.levels[0].id;
+    }
+    this._renderLevels();
+  }
+
+  _renderLevels() {
+    if (!this.levelSelect) return;
+    this.levelSelect.innerHTML = "";
+    this.levels.forEach((lvl) => {
+      const id = typeof lvl === "string" ? lvl : lvl.id;
+      const opt = document.createElement("option");
+      opt.value = id;
+      opt.textContent = typeof lvl === "string" ? lvl : lvl.label;
+      if (id === this.selectedLevel) opt.selected = true;
+      this.levelSelect.appendChild(opt);
+    });
+  }
+
   setRooms(rooms) {
     this.rooms = Array.isArray(rooms) ? rooms.slice() : [];
     this._renderRooms();
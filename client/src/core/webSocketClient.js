@@ -1,3 +1,6 @@
+// Protocol version announced in the `hello` handshake.
+const PROTOCOL_VERSION = 2;
+
 export class WebSocketClient {
   constructor(url, options = {}) {
     this.url = url;
@@ -13,6 +16,9 @@ export class WebSocketClient {
     this.isConnected = false;
     this.myPlayerId = null; // Store the player ID from welcome message
 
+    // Recent full states by seq, used as bases for server delta snapshots
+    this.snapshots = new Map();
+
     // read token from localStorage (if present)
     this.clientTokenKey = this.options.clientTokenKey || "zuma_token";
     this.token = localStorage.getItem(this.clientTokenKey);
@@ -41,6 +47,9 @@ export class WebSocketClient {
       // Initialize activity tracker immediately on connect
       this.lastPong = Date.now();
 
+      // Handshake must precede every other message
+      this.send({ type: "hello", version: PROTOCOL_VERSION });
+
       // Flush queued messages
       while (this.messageQueue.length > 0) {
         const message = this.messageQueue.shift();
@@ -85,6 +94,12 @@ export class WebSocketClient {
         return;
       }
 
+      // Handle level list response
+      if (data && data.type === "levels_list") {
+        this.trigger("levels_list", data.levels);
+        return;
+      }
+
       // Handle room created response
       if (data && data.type === "room_created") {
         this.trigger("room_created", data);
@@ -104,6 +119,15 @@ export class WebSocketClient {
         return;
       }
 
+      // Rebuild full state from deltas so handlers only ever see "state"
+      if (data && data.type === "delta") {
+        data = this.applyDelta(data);
+        if (!data) return;
+      }
+      if (data && data.type === "state" && typeof data.seq === "number") {
+        this.rememberSnapshot(data);
+      }
+
       // Trigger custom message handlers
       this.trigger("message", data);
 
@@ -150,6 +174,24 @@ export class WebSocketClient {
     this.send({ type: "list_rooms" });
   }
 
+  // Room lobby: toggle ready, and (host only) start the match
+  setReady(ready) {
+    this.send({ type: "ready", ready: !!ready });
+  }
+
+  startMatch() {
+    this.send({ type: "start_match" });
+  }
+
+  // Play the room's level again after a game over
+  restart() {
+    this.send({ type: "restart" });
+  }
+
+  listLevels() {
+    this.send({ type: "list_levels" });
+  }
+
   createRoom(name, maxPlayers = 4, level = null) {
     this.send({
       type: "create_room",
@@ -160,6 +202,7 @@ export class WebSocketClient {
   }
 
   joinRoom(roomId) {
+    this.snapshots.clear();
     this.send({
       type: "join_room",
       roomId: roomId,
@@ -167,6 +210,71 @@ export class WebSocketClient {
     });
   }
 
+  // Keep a full state so later deltas can be applied to it, then ack it
+  rememberSnapshot(state) {
+    this.snapshots.set(state.seq, state);
+    for (const seq of this.snapshots.keys()) {
+      if (seq < state.seq - 64) this.snapshots.delete(seq);
+    }
+    this.send({ type: "ack", seq: state.seq });
+  }
+
+  // Apply a server delta to its acknowledged base; returns the full state
+  applyDelta(delta) {
+    const base = this.snapshots.get(delta.base_seq);
+    if (!base) {
+      this.send({ type: "request_keyframe" });
+      return null;
+    }
+
+    const removedPlayers = new Set(delta.players_removed);
+    const players = new Map(
+      base.players.filter((p) => !removedPlayers.has(p.id)).map((p) => [p.id, p]),
+    );
+    for (const p of delta.players_changed) players.set(p.id, p);
+
+    const removedMarbles = new Set(delta.marbles_removed);
+    const marbles = new Map(
+      base.marbles
+        .filter((m) => !removedMarbles.has(m.id))
+        .map((m) => [m.id, m]),
+    );
+    for (const change of delta.marbles_changed) {
+      const m = marbles.get(change.id);
+      if (m) marbles.set(change.id, { ...m, ...change });
+    }
+    for (const m of delta.marbles_added) marbles.set(m.id, m);
+
+    const removedChain = new Set(delta.chain_removed);
+    const chain = new Map(
+      (base.chain || [])
+        .filter((c) => !removedChain.has(c.id))
+        .map((c) => [c.id, c]),
+    );
+    for (const change of delta.chain_changed) {
+      const c = chain.get(change.id);
+      if (c) chain.set(change.id, { ...c, ...change });
+    }
+    for (const c of delta.chain_added) chain.set(c.id, c);
+
+    return {
+      type: "state",
+      seq: delta.seq,
+      tick: delta.tick,
+      players: [...players.values()],
+      marbles: [...marbles.values()],
+      chain: [...chain.values()].sort((a, b) => a.s - b.s),
+      score: delta.score,
+      game_over: delta.game_over,
+      outcome: delta.outcome,
+      campaign: delta.campaign,
+      phase: delta.phase,
+      countdown: delta.countdown,
+      marbles_reached_end: delta.marbles_reached_end,
+      difficulty: delta.difficulty,
+    };
+  }
+
   startHeartbeat() {
     this.stopHeartbeat();
     this.lastPong = Date.now();
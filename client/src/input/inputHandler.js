@@ -21,6 +21,18 @@ export class InputHandler {
 
     domElement.addEventListener("pointermove", (e) => this.onPointerMove(e));
     domElement.addEventListener("click", () => this.onShoot());
+    // right click or space swaps the loaded and next marble
+    domElement.addEventListener("contextmenu", (e) => {
+      e.preventDefault();
+      this.onSwap();
+    });
+    window.addEventListener("keydown", (e) => {
+      if (e.target instanceof HTMLInputElement) return;
+      if (e.code === "Space" && !e.repeat) {
+        e.preventDefault();
+        this.onSwap();
+      }
+    });
   }
 
   setPlayerPosition(x, y, z) {
@@ -69,4 +81,10 @@ export class InputHandler {
       type: "shoot",
     });
   }
+
+  onSwap() {
+    this.network.send({
+      type: "swap",
+    });
+  }
 }
@@ -1,10 +1,13 @@
 import * as THREE from "three";
 import { GLTFLoader } from "three/examples/jsm/Addons.js";
 
+// Server marble heights are this far above the level curve; marbles are drawn on the curve
+const CHAIN_HEIGHT = 0.5;
+
 export class MarbleRenderer {
   constructor(scene) {
     this.scene = scene;
-    this.marbles = new Map(); // id -> { mesh, targetPos, currentPos, velocity }
+    this.marbles = new Map(); // id -> { mesh, targetPos, currentPos, velocity, targetS?, currentS?, track? }
     this.geometry = new THREE.SphereGeometry(0.2, 8, 8);
     this.material = new THREE.MeshStandardMaterial({ color: 0xffcc00 });
 
@@ -14,69 +17,151 @@ export class MarbleRenderer {
     this.yellow = new THREE.MeshStandardMaterial({ color: 0xffff00 });
     this.purple = new THREE.MeshStandardMaterial({ color: 0xff00ff });
 
+    // Glowing variants for power-up chain marbles, by color
+    this.powerMaterials = new Map();
+
+    // Sampled level paths from the server's "path" messages (protocol v2), by track
+    this.paths = [];
+
     // Interpolation settings
     this.interpolationSpeed = 10; // Higher = snappier, lower = smoother
   }
 
-  update(marblesArray) {
+  // Store a track's path so its chain marbles can be placed by their `s`
+  setPath(points, totalLength, track = 0) {
+    const pts = Array.isArray(points) ? points : [];
+    const lengths = [];
+    let acc = 0;
+    for (let i = 0; i < pts.length; i++) {
+      if (i > 0) {
+        const [ax, ay, az] = pts[i - 1];
+        const [bx, by, bz] = pts[i];
+        acc += Math.hypot(bx - ax, by - ay, bz - az);
+      }
+      lengths.push(acc);
+    }
+    this.paths[track] = { points: pts, lengths, total: totalLength || acc || 1 };
+  }
+
+  // Map arc-fraction s in [0..1] to a world position along a track's path
+  pathPosition(s, out, track = 0) {
+    const path = this.paths[track];
+    if (!path || path.points.length === 0) return out.set(0, 0, 0);
+    const pts = path.points;
+    const target = Math.min(Math.max(s, 0), 1) * path.total;
+    let lo = 0;
+    let hi = path.lengths.length - 1;
+    while (lo < hi) {
+      const mid = (lo + hi) >> 1;
+      if (path.lengths[mid] < target) lo = mid + 1;
+      else hi = mid;
+    }
+    if (lo === 0) return out.set(pts[0][0], pts[0][1] - CHAIN_HEIGHT, pts[0][2]);
+    const l1 = path.lengths[lo - 1];
+    const l2 = path.lengths[lo];
+    const t = (target - l1) / Math.max(l2 - l1, 1e-6);
+    const a = pts[lo - 1];
+    const b = pts[lo];
+    return out.set(
+      a[0] + (b[0] - a[0]) * t,
+      a[1] + (b[1] - a[1]) * t - CHAIN_HEIGHT,
+      a[2] + (b[2] - a[2]) * t,
+    );
+  }
+
+  materialFor(color, power) {
+    if (power) {
+      let mat = this.powerMaterials.get(color);
+      if (!mat) {
+        mat = this.materialFor(color).clone();
+        mat.emissive = new THREE.Color(0xffffff);
+        mat.emissiveIntensity = 0.6;
+        this.powerMaterials.set(color, mat);
+      }
+      return mat;
+    }
+    switch (color) {
+      case "red":
+        return this.red;
+      case "blue":
+        return this.blue;
+      case "green":
+        return this.green;
+      case "yellow":
+        return this.yellow;
+      case "purple":
+        return this.purple;
+      default:
+        return this.material;
+    }
+  }
+
+  createEntry(id, color, position, power) {
+    const mesh = new THREE.Mesh(this.geometry, this.materialFor(color, power));
+    mesh.castShadow = true;
+    mesh.receiveShadow = false;
+    this.scene.add(mesh);
+
+    // Initialize with current position (no interpolation on first frame)
+    const entry = {
+      mesh,
+      targetPos: position.clone(),
+      currentPos: position.clone(),
+      velocity: new THREE.Vector3(0, 0, 0),
+    };
+    mesh.position.copy(entry.currentPos);
+    this.marbles.set(id, entry);
+    return entry;
+  }
+
+  update(marblesArray, chainArray) {
     if (!Array.isArray(marblesArray)) marblesArray = [];
+    if (!Array.isArray(chainArray)) chainArray = [];
 
     const seen = new Set();
+    const pos = new THREE.Vector3();
 
     for (const m of marblesArray) {
       if (!m || typeof m.id === "undefined") continue;
       seen.add(m.id);
 
+      const x = typeof m.x === "number" ? m.x : 0;
+      const y = typeof m.y === "number" ? m.y - CHAIN_HEIGHT : 0;
+      const z = typeof m.z === "number" ? m.z : 0;
+      pos.set(x, y, z);
+
       let entry = this.marbles.get(m.id);
       if (!entry) {
-        var entryColor;
-
-        switch (m.color) {
-          case "red":
-            entryColor = this.red;
-            break;
-          case "blue":
-            entryColor = this.blue;
-            break;
-          case "green":
-            entryColor = this.green;
-            break;
-          case "yellow":
-            entryColor = this.yellow;
-            break;
-          case "purple":
-            entryColor = this.purple;
-            break;
-          default:
-            entryColor = this.material;
-        }
-
-        const mesh = new THREE.Mesh(this.geometry, entryColor);
-        mesh.castShadow = true;
-        mesh.receiveShadow = false;
-        this.scene.add(mesh);
-
-        const x = typeof m.x === "number" ? m.x : 0;
-        const y = 0;
-        const z = typeof m.z === "number" ? m.z : 0;
-
-        // Initialize with current position (no interpolation on first frame)
-        entry = {
-          mesh,
-          targetPos: new THREE.Vector3(x, y, z),
-          currentPos: new THREE.Vector3(x, y, z),
-          velocity: new THREE.Vector3(0, 0, 0),
-        };
-        mesh.position.copy(entry.currentPos);
-        this.marbles.set(m.id, entry);
+        this.createEntry(m.id, m.color, pos);
       } else {
         // Update target position from server
-        const x = typeof m.x === "number" ? m.x : 0;
-        const y = 0;
-        const z = typeof m.z === "number" ? m.z : 0;
+        entry.targetPos.copy(pos);
+      }
+    }
+
+    // Chain marbles arrive as path fractions; interpolate along the curve
+    for (const c of chainArray) {
+      if (!c || typeof c.id === "undefined") continue;
+      seen.add(c.id);
 
-        entry.targetPos.set(x, y, z);
+      const track = c.track || 0;
+      let entry = this.marbles.get(c.id);
+      if (!entry) {
+        entry = this.createEntry(
+          c.id,
+          c.color,
+          this.pathPosition(c.s, pos, track),
+          c.power,
+        );
+        entry.currentS = c.s;
+      } else if (typeof entry.targetS !== "number") {
+        // Free marble that was just inserted into the chain
+        entry.currentS = c.s;
       }
+      entry.targetS = c.s;
+      entry.track = track;
+      // Out of sight inside a tunnel
+      entry.mesh.visible = !c.hidden;
     }
 
     // Remove marbles that no longer exist on server
@@ -92,9 +177,17 @@ export class MarbleRenderer {
 
   // Call this every frame to smoothly interpolate positions
   interpolate(dt) {
+    // Smooth interpolation using lerp
+    const alpha = Math.min(1, this.interpolationSpeed * dt);
+
     for (const [id, entry] of this.marbles.entries()) {
-      // Smooth interpolation using lerp
-      const alpha = Math.min(1, this.interpolationSpeed * dt);
+      if (typeof entry.targetS === "number") {
+        entry.currentS += (entry.targetS - entry.currentS) * alpha;
+        this.pathPosition(entry.currentS, entry.targetPos, entry.track);
+        entry.currentPos.copy(entry.targetPos);
+        entry.mesh.position.copy(entry.currentPos);
+        continue;
+      }
 
       entry.currentPos.lerp(entry.targetPos, alpha);
       entry.mesh.position.copy(entry.currentPos);
@@ -78,8 +78,10 @@ export default class SceneManager {
   _levelKeyToGlbPath(levelKey) {
     switch (levelKey) {
       case "first-level":
+      case "first-level-short":
         return "./assets/firstLevel.glb";
       case "second-level":
+      case "second-level-clear":
         return "./assets/secondLevel.glb";
       default:
         // safe fallback
@@ -141,7 +143,7 @@ export default class SceneManager {
       this.playerRenderer.update(gameState.players);
     }
     if (gameState && gameState.marbles) {
-      this.marbleRenderer.update(gameState.marbles);
+      this.marbleRenderer.update(gameState.marbles, gameState.chain);
     }
 
     // Interpolate positions every frame for smooth movement
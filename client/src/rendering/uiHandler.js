@@ -117,6 +117,61 @@ export class UIHandler {
         display:block;
         margin-top: 6px;
       }
+      .ui-gameover .restart-btn {
+        margin-top: 14px;
+        padding: 8px 18px;
+        border-radius: 8px;
+        border: 0;
+        cursor: pointer;
+        font-weight: 700;
+      }
+
+      /* Room lobby / countdown panel (top-center) */
+      .ui-lobby {
+        position: absolute;
+        top: var(--ui-gap);
+        left: 50%;
+        transform: translateX(-50%);
+        display: none;
+        pointer-events: auto;
+        background: rgba(0,0,0,0.7);
+        color: #fff;
+        padding: 12px 16px;
+        border-radius: var(--ui-radius);
+        min-width: 240px;
+        box-shadow: 0 8px 20px rgba(0,0,0,0.45);
+        font-size: var(--ui-small-size);
+      }
+      .ui-lobby.visible { display: block; }
+      .ui-lobby .title { font-size: var(--ui-font-size); font-weight: 700; margin-bottom: 8px; }
+      .ui-lobby ul { list-style: none; margin: 0 0 10px; padding: 0; }
+      .ui-lobby li { display: flex; justify-content: space-between; gap: 12px; padding: 2px 0; }
+      .ui-lobby .ready { color: #7ddc7d; }
+      .ui-lobby .actions { display: flex; gap: 8px; }
+      .ui-lobby button { flex: 1; padding: 6px 10px; border-radius: 8px; border: 0; cursor: pointer; }
+      .ui-lobby .countdown { font-size: 36px; font-weight: 900; text-align: center; }
+
+      /* Gameplay event toasts (power-ups, ...) */
+      .ui-events {
+        position: absolute;
+        top: 30%;
+        left: 50%;
+        transform: translateX(-50%);
+        display: flex;
+        flex-direction: column;
+        align-items: center;
+        gap: 6px;
+        pointer-events: none;
+      }
+      .ui-events .toast {
+        background: rgba(0,0,0,0.7);
+        color: #ffe066;
+        padding: 8px 14px;
+        border-radius: var(--ui-radius);
+        font-size: 24px;
+        font-weight: 800;
+        transition: opacity 0.4s;
+      }
     `;
     document.head.appendChild(style);
   }
@@ -140,19 +195,50 @@ export class UIHandler {
       <div class="panel">
         <div class="title">GAME OVER</div>
         <div class="subtitle">
-          Too many marbles reached the end.
+          <span class="reason">Too many marbles reached the end.</span>
           <span class="muted"></span>
         </div>
         <div class="subtitle">
           Final score: <span class="final-score">0</span>
         </div>
+        <button class="restart-btn">Play again</button>
       </div>
     `;
+    this.gameOverTitleEl = this.gameOverOverlay.querySelector(".title");
+    this.gameOverReasonEl = this.gameOverOverlay.querySelector(".reason");
     this.gameOverSubtitleEl = this.gameOverOverlay.querySelector(".muted");
+    // level's end-of-path limit, learned from `round_summary`
+    this.endThreshold = 10;
     this.gameOverFinalScoreEl =
       this.gameOverOverlay.querySelector(".final-score");
+    this.restartBtn = this.gameOverOverlay.querySelector(".restart-btn");
     this.container.appendChild(this.gameOverOverlay);
 
+    // room lobby (top-center): players, ready toggle, host start, countdown
+    this.lobbyPanel = document.createElement("div");
+    this.lobbyPanel.className = "ui-lobby";
+    this.lobbyPanel.innerHTML = `
+      <div class="title">Waiting for players</div>
+      <ul></ul>
+      <div class="actions">
+        <button class="ready-btn">Ready</button>
+        <button class="start-btn">Start</button>
+      </div>
+      <div class="countdown"></div>
+    `;
+    this.lobbyTitleEl = this.lobbyPanel.querySelector(".title");
+    this.lobbyListEl = this.lobbyPanel.querySelector("ul");
+    this.lobbyActionsEl = this.lobbyPanel.querySelector(".actions");
+    this.readyBtn = this.lobbyPanel.querySelector(".ready-btn");
+    this.startBtn = this.lobbyPanel.querySelector(".start-btn");
+    this.countdownEl = this.lobbyPanel.querySelector(".countdown");
+    this.container.appendChild(this.lobbyPanel);
+
+    // event toasts (center)
+    this.eventsEl = document.createElement("div");
+    this.eventsEl.className = "ui-events";
+    this.container.appendChild(this.eventsEl);
+
     this.parent.appendChild(this.container);
   }
 
@@ -167,13 +253,23 @@ export class UIHandler {
     const on = !!isGameOver;
     this.gameOverOverlay.classList.toggle("visible", on);
 
+    const victory = opts.outcome === "victory";
+    if (this.gameOverTitleEl) {
+      this.gameOverTitleEl.textContent = victory ? "LEVEL CLEAR" : "GAME OVER";
+    }
+    if (this.gameOverReasonEl) {
+      this.gameOverReasonEl.textContent = victory
+        ? "The chain has been cleared."
+        : "Too many marbles reached the end.";
+    }
+
     if (this.gameOverSubtitleEl) {
       const reached =
         typeof opts.marblesReachedEnd === "number"
           ? opts.marblesReachedEnd
           : null;
       const threshold =
-        typeof opts.threshold === "number" ? opts.threshold : 10;
+        typeof opts.threshold === "number" ? opts.threshold : this.endThreshold;
 
       if (reached != null) {
         this.gameOverSubtitleEl.textContent = `Reached end: ${reached}/${threshold}`;
@@ -196,6 +292,82 @@ export class UIHandler {
     }
   }
 
+  // Show the room lobby from a server `room_state` message
+  setRoomState(roomState, myPlayerId) {
+    if (!this.lobbyPanel || !roomState) return;
+    this.roomState = roomState;
+    const players = Array.isArray(roomState.players) ? roomState.players : [];
+    this.lobbyListEl.innerHTML = "";
+    for (const p of players) {
+      const li = document.createElement("li");
+      const name = document.createElement("span");
+      name.textContent = p.id === roomState.host ? `${p.name} (host)` : p.name;
+      const status = document.createElement("span");
+      status.textContent = p.ready ? "ready" : "not ready";
+      if (p.ready) status.className = "ready";
+      li.appendChild(name);
+      li.appendChild(status);
+      this.lobbyListEl.appendChild(li);
+    }
+    const me = players.find((p) => p.id === myPlayerId);
+    this.isReady = !!(me && me.ready);
+    this.readyBtn.textContent = this.isReady ? "Not ready" : "Ready";
+    this.startBtn.style.display =
+      myPlayerId != null && myPlayerId === roomState.host ? "" : "none";
+    this.setPhase(roomState.phase);
+  }
+
+  // Lobby is shown while waiting; countdown replaces the buttons
+  setPhase(phase, countdown) {
+    if (!this.lobbyPanel) return;
+    const waiting = phase === "waiting";
+    const counting = phase === "countdown";
+    this.lobbyPanel.classList.toggle("visible", waiting || counting);
+    this.lobbyActionsEl.style.display = waiting ? "" : "none";
+    this.lobbyTitleEl.textContent = counting
+      ? "Get ready!"
+      : "Waiting for players";
+    this.countdownEl.textContent =
+      counting && typeof countdown === "number"
+        ? String(Math.ceil(countdown))
+        : "";
+  }
+
+  // Briefly show a gameplay event message
+  showEvent(text, ms = 1600) {
+    if (!this.eventsEl) return;
+    const toast = document.createElement("div");
+    toast.className = "toast";
+    toast.textContent = text;
+    this.eventsEl.appendChild(toast);
+    setTimeout(() => {
+      toast.style.opacity = "0";
+      setTimeout(() => toast.remove(), 400);
+    }, ms);
+  }
+
+  // Text for an event from the server's `events` message
+  describeEvent(ev) {
+    if (!ev) return null;
+    if (ev.kind === "combo") {
+      return `Combo x${ev.multiplier}! +${ev.score}`;
+    }
+    if (ev.kind === "power_up") {
+      const removed = Array.isArray(ev.removed) ? ev.removed.length : 0;
+      switch (ev.power) {
+        case "slow_down":
+          return "Slow down!";
+        case "reverse":
+          return "Reverse!";
+        case "bomb":
+          return `Bomb! +${removed * 10}`;
+        case "color_blast":
+          return `Color blast: ${ev.color}! +${removed * 10}`;
+      }
+    }
+    return null;
+  }
+
   // small helper to randomize score 0..300
   randomizeScore() {
     this.setScore(this._randomScore());
@@ -212,6 +384,9 @@ export class UIHandler {
     // Preferred: listen to already-parsed typed events (e.g. {type:"state", score: ...})
     wsClient.on("state", (state) => {
       if (!state) return;
+      if (typeof state.phase === "string") {
+        this.setPhase(state.phase, state.countdown);
+      }
       if (typeof state.score === "number") {
         this.setScore(state.score);
       }
@@ -225,7 +400,8 @@ export class UIHandler {
               : typeof state.marbles_reached_end === "string"
                 ? Number(state.marbles_reached_end)
                 : null,
-          threshold: 10,
+          threshold: this.endThreshold,
+          outcome: state.outcome,
           score: state.score,
         });
       }
@@ -248,7 +424,8 @@ export class UIHandler {
                 : typeof data.marbles_reached_end === "string"
                   ? Number(data.marbles_reached_end)
                   : null,
-            threshold: 10,
+            threshold: this.endThreshold,
+            outcome: data.outcome,
             score: data.score,
           });
         }
@@ -261,6 +438,58 @@ export class UIHandler {
       }
     });
 
+    // end-of-round report (victory or defeat), sent once
+    wsClient.on("round_summary", (summary) => {
+      if (!summary) return;
+      if (typeof summary.end_threshold === "number") {
+        this.endThreshold = summary.end_threshold;
+      }
+      // a won campaign stage goes straight on to the next one
+      const stage = summary.campaign;
+      if (
+        summary.outcome === "victory" &&
+        stage &&
+        stage.stage + 1 < stage.stages
+      ) {
+        this.showEvent(
+          `Stage ${stage.stage + 1}/${stage.stages} cleared`,
+          2500,
+        );
+      } else {
+        this.setGameOver(true, {
+          marblesReachedEnd: summary.marbles_reached_end,
+          threshold: this.endThreshold,
+          outcome: summary.outcome,
+          score: summary.score,
+        });
+      }
+      if (summary.time_bonus > 0) {
+        this.showEvent(`Time bonus +${summary.time_bonus}`);
+      }
+    });
+
+    wsClient.on("events", (msg) => {
+      if (!msg || !Array.isArray(msg.events)) return;
+      for (const ev of msg.events) {
+        const text = this.describeEvent(ev);
+        if (text) this.showEvent(text);
+      }
+    });
+
+    wsClient.on("room_state", (roomState) => {
+      this.setRoomState(roomState, wsClient.myPlayerId);
+    });
+    if (this.readyBtn) {
+      this.readyBtn.onclick = () => wsClient.setReady(!this.isReady);
+    }
+    if (this.startBtn) {
+      this.startBtn.onclick = () => wsClient.startMatch();
+    }
+    // back to the room lobby on the same level, without recreating the room
+    if (this.restartBtn) {
+      this.restartBtn.onclick = () => wsClient.restart();
+    }
+
     wsClient.on("welcome", (_data) => {
       // Welcome messages are optional; don't randomize score because we want server-authoritative score.
       // If you re-enable chat later, you can uncomment:
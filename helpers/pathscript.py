@@ -3,40 +3,92 @@ import json
 from mathutils import Vector
 
 # SETTINGS
-CURVE_NAME = "BezierCurve"
+# One curve per track; levels with more than one export "tracks"
+CURVE_NAMES = ["BezierCurve"]
 OUTPUT_PATH = "//zuma_path.json"
-
-curve_obj = bpy.data.objects[CURVE_NAME]
+# Level picker metadata (difficulty: "easy" | "normal" | "hard")
+DISPLAY_NAME = "New Level"
+DIFFICULTY = "normal"
+RECOMMENDED_PLAYERS = 2
+# Arc length per second a gap closes when both ends share a color (0 = off)
+MAGNET_PULL_SPEED = 1.5
+# Clear mode: total marbles spawned (None = endless), marbles allowed to reach
+# the end before losing, and seconds under which a clear earns a time bonus
+# (second-level-clear exports MARBLE_BUDGET = 90, PAR_TIME = 150)
+MARBLE_BUDGET = None
+END_THRESHOLD = 10
+PAR_TIME = 120
+# Seconds between loaded/next swaps (None = server default, 0 = no cooldown)
+SWAP_COOLDOWN = None
+# Chain generation overrides (empty = server defaults), e.g.
+# {"palette": ["red", "blue", "yellow"], "initial_length": 15,
+#  "spawn_interval": {"start": 0.75, "end": 0.55, "min": 0.3},
+#  "repeat": {"chance": 0.6, "look_back": 10}, "shot_color_bias": 0.5}
+CHAIN = {}
+# Stretches of a curve where the chain is out of sight and can't be hit, as
+# fractions of its length, e.g. {"BezierCurve": [{"start": 0.4, "end": 0.55}]}
+TUNNELS = {}
+# Scenery that stops shots (or deflects them with "bounce": true), in ground-plane
+# (x, z) coordinates, e.g.
+# [{"shape": "circle", "center": [0, 3], "radius": 0.5},
+#  {"shape": "segment", "from": [-1, -3], "to": [1, -3], "bounce": True},
+#  {"shape": "polygon", "points": [[3, -1], [5, -1], [5, 1], [3, 1]]}]
+COLLIDERS = []
 
 depsgraph = bpy.context.evaluated_depsgraph_get()
-eval_obj = curve_obj.evaluated_get(depsgraph)
 
-# Convert evaluated curve to mesh
-mesh = eval_obj.to_mesh()
 
-points = []
+def curve_points(name):
+    curve_obj = bpy.data.objects[name]
+    eval_obj = curve_obj.evaluated_get(depsgraph)
+
+    # Convert evaluated curve to mesh
+    mesh = eval_obj.to_mesh()
+
+    points = []
+
+    # Extract vertices in order
+    for v in mesh.vertices:
+        world_pos = curve_obj.matrix_world @ v.co
+        points.append([world_pos.x, world_pos.z, -world_pos.y])
+
+    # Cleanup
+    eval_obj.to_mesh_clear()
+
+    #Remove the last point if the same
+    if points[0] == points[-1]:
+        points.pop(-1)
 
-# Extract vertices in order
-for v in mesh.vertices:
-    world_pos = curve_obj.matrix_world @ v.co
-    points.append([world_pos.x, world_pos.z, -world_pos.y])
+    #reverse if needed
+    points.reverse()
+    return points
 
-# Cleanup
-eval_obj.to_mesh_clear()
 
-#Remove the last point if the same
-if points[0] == points[-1]:
-    points.pop(-1)
-    
-#reverse if needed
-points.reverse()
+tracks = [curve_points(name) for name in CURVE_NAMES]
 
 data = {
-    "name": CURVE_NAME,
-    "points": points
+    "name": CURVE_NAMES[0],
+    "display_name": DISPLAY_NAME,
+    "difficulty": DIFFICULTY,
+    "recommended_players": RECOMMENDED_PLAYERS,
+    "magnet_pull_speed": MAGNET_PULL_SPEED,
+    "marble_budget": MARBLE_BUDGET,
+    "end_threshold": END_THRESHOLD,
+    "par_time": PAR_TIME,
+    "swap_cooldown": SWAP_COOLDOWN,
+    "chain": CHAIN,
+    "colliders": COLLIDERS,
 }
+if len(tracks) == 1:
+    data["points"] = tracks[0]
+    data["tunnels"] = TUNNELS.get(CURVE_NAMES[0], [])
+else:
+    data["tracks"] = [
+        {"points": points, "tunnels": TUNNELS.get(name, [])}
+        for name, points in zip(CURVE_NAMES, tracks)
+    ]
 
 with open(bpy.path.abspath(OUTPUT_PATH), "w") as f:
     json.dump(data, f, indent=2)
 
-print(f"Exported {len(points)} points to {OUTPUT_PATH}")
+print(f"Exported {sum(len(t) for t in tracks)} points on {len(tracks)} tracks to {OUTPUT_PATH}")
@@ -0,0 +1,197 @@
+use clap::Parser;
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Config file read when `--config` is not given (skipped if it does not exist).
+const DEFAULT_CONFIG_FILE: &str = "server.toml";
+
+/// Server settings. Built from defaults, then a TOML file, then env vars and CLI
+/// flags (CLI wins over env).
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Address the HTTP/WebSocket server listens on.
+    pub bind_addr: String,
+    /// Simulation/broadcast step for every room, in milliseconds.
+    pub tick_rate_ms: u64,
+    /// How often empty rooms are removed, in seconds.
+    pub cleanup_interval_secs: u64,
+    /// How long an empty room with a paused match is kept for its players to
+    /// rejoin, in seconds.
+    pub paused_room_grace_secs: u64,
+    /// Directory holding the level path files (`<level>.json`).
+    pub paths_dir: PathBuf,
+    /// Most rooms that may exist at once; further `create_room` requests fail.
+    pub max_rooms: usize,
+    /// Upper bound for a room's player cap, whatever the client asks for.
+    pub max_players_per_room: usize,
+    pub lobby: LobbyConfig,
+}
+
+/// Room created at startup so players have somewhere to go.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LobbyConfig {
+    pub enabled: bool,
+    pub name: String,
+    pub max_players: usize,
+    pub level: String,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            bind_addr: "0.0.0.0:8080".to_string(),
+            tick_rate_ms: 50,
+            cleanup_interval_secs: 60,
+            paused_room_grace_secs: 300,
+            paths_dir: PathBuf::from("paths"),
+            max_rooms: 64,
+            max_players_per_room: 8,
+            lobby: LobbyConfig::default(),
+        }
+    }
+}
+
+impl Default for LobbyConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            name: "Lobby".to_string(),
+            max_players: 8,
+            level: "first-level".to_string(),
+        }
+    }
+}
+
+/// Command line flags; each can also be set through the listed env var.
+#[derive(Debug, Parser)]
+#[command(about = "MarbleChain game server")]
+struct Cli {
+    /// TOML config file (default: ./server.toml if present)
+    #[arg(long, short, env = "MARBLE_CONFIG")]
+    config: Option<PathBuf>,
+    /// Address to listen on, e.g. 0.0.0.0:8080
+    #[arg(long, env = "MARBLE_BIND_ADDR")]
+    bind_addr: Option<String>,
+    /// Simulation step in milliseconds
+    #[arg(long, env = "MARBLE_TICK_RATE_MS")]
+    tick_rate_ms: Option<u64>,
+    /// Seconds between empty-room sweeps
+    #[arg(long, env = "MARBLE_CLEANUP_INTERVAL_SECS")]
+    cleanup_interval_secs: Option<u64>,
+    /// Seconds an empty room with a paused match waits for players to rejoin
+    #[arg(long, env = "MARBLE_PAUSED_ROOM_GRACE_SECS")]
+    paused_room_grace_secs: Option<u64>,
+    /// Directory with level path files
+    #[arg(long, env = "MARBLE_PATHS_DIR")]
+    paths_dir: Option<PathBuf>,
+    /// Most rooms that may exist at once
+    #[arg(long, env = "MARBLE_MAX_ROOMS")]
+    max_rooms: Option<usize>,
+    /// Upper bound for any room's player cap
+    #[arg(long, env = "MARBLE_MAX_PLAYERS_PER_ROOM")]
+    max_players_per_room: Option<usize>,
+    /// Don't create the default lobby room at startup
+    #[arg(long, env = "MARBLE_NO_LOBBY")]
+    no_lobby: bool,
+    /// Name of the default lobby room
+    #[arg(long, env = "MARBLE_LOBBY_NAME")]
+    lobby_name: Option<String>,
+    /// Player cap of the default lobby room
+    #[arg(long, env = "MARBLE_LOBBY_MAX_PLAYERS")]
+    lobby_max_players: Option<usize>,
+    /// Level of the default lobby room
+    #[arg(long, env = "MARBLE_LOBBY_LEVEL")]
+    lobby_level: Option<String>,
+}
+
+impl Config {
+    /// Load the config for this process from its args, environment and config file.
+    pub fn load() -> Result<Self, String> {
+        let cli = Cli::parse();
+
+        let mut config = match &cli.config {
+            Some(path) => Self::from_file(path)?,
+            None if Path::new(DEFAULT_CONFIG_FILE).exists() => {
+                Self::from_file(Path::new(DEFAULT_CONFIG_FILE))?
+            }
+            None => Self::default(),
+        };
+
+        if let Some(v) = cli.bind_addr {
+            config.bind_addr = v;
+        }
+        if let Some(v) = cli.tick_rate_ms {
+            config.tick_rate_ms = v;
+        }
+        if let Some(v) = cli.cleanup_interval_secs {
+            config.cleanup_interval_secs = v;
+        }
+        if let Some(v) = cli.paused_room_grace_secs {
+            config.paused_room_grace_secs = v;
+        }
+        if let Some(v) = cli.paths_dir {
+            config.paths_dir = v;
+        }
+        if let Some(v) = cli.max_rooms {
+            config.max_rooms = v;
+        }
+        if let Some(v) = cli.max_players_per_room {
+            config.max_players_per_room = v;
+        }
+        if cli.no_lobby {
+            config.lobby.enabled = false;
+        }
+        if let Some(v) = cli.lobby_name {
+            config.lobby.name = v;
+        }
+        if let Some(v) = cli.lobby_max_players {
+            config.lobby.max_players = v;
+        }
+        if let Some(v) = cli.lobby_level {
+            config.lobby.level = v;
+        }
+
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn from_file(path: &Path) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| format!("failed to read config {}: {}", path.display(), e))?;
+        toml::from_str(&text).map_err(|e| format!("invalid config {}: {}", path.display(), e))
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if self.tick_rate_ms == 0 {
+            return Err("tick_rate_ms must be greater than 0".to_string());
+        }
+        if self.cleanup_interval_secs == 0 {
+            return Err("cleanup_interval_secs must be greater than 0".to_string());
+        }
+        if self.max_players_per_room == 0 {
+            return Err("max_players_per_room must be greater than 0".to_string());
+        }
+        if self.lobby.max_players == 0 || self.lobby.max_players > self.max_players_per_room {
+            return Err(format!(
+                "lobby.max_players must be between 1 and max_players_per_room ({})",
+                self.max_players_per_room
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn tick_rate(&self) -> Duration {
+        Duration::from_millis(self.tick_rate_ms)
+    }
+
+    pub fn cleanup_interval(&self) -> Duration {
+        Duration::from_secs(self.cleanup_interval_secs)
+    }
+
+    pub fn paused_room_grace(&self) -> Duration {
+        Duration::from_secs(self.paused_room_grace_secs)
+    }
+}
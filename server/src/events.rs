@@ -0,0 +1,41 @@
+use crate::game::{MarbleColor, PowerUp};
+use serde::Serialize;
+
+/// Something that happened during a simulation step that clients should show
+/// (sent once in an `events` message, unlike state which is resent every tick).
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum GameEvent {
+    /// A power-up marble was popped in a match on `track`, at `s` along it.
+    /// `removed` lists chain marbles destroyed by the effect itself (bomb,
+    /// color blast); timed effects (slow-down, reverse) report how long they last.
+    PowerUp {
+        track: usize,
+        power: PowerUp,
+        color: MarbleColor,
+        s: f32,
+        removed: Vec<u64>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        duration: Option<f32>,
+    },
+    /// A chain reaction on `track`: the marbles meeting at a closed gap formed
+    /// a run and popped. `level` counts cascades since the shot (1 = first
+    /// reaction); `score` is what this step added, already multiplied.
+    Combo {
+        track: usize,
+        level: u32,
+        multiplier: u32,
+        color: MarbleColor,
+        removed: Vec<u64>,
+        score: u32,
+    },
+    /// A shot hit level scenery (`obstacle` indexes the level's colliders) at
+    /// `(x, z)`. It stopped there unless `bounced`.
+    ObstacleHit {
+        marble: u64,
+        obstacle: usize,
+        x: f32,
+        z: f32,
+        bounced: bool,
+    },
+}
@@ -1,9 +1,14 @@
+use crate::events::GameEvent;
+use crate::level::{ChainRules, LevelError, LevelFile, TunnelRange};
+use crate::obstacle::{self, Collider};
+use crate::path::{SampledPath, CHAIN_HEIGHT};
+use crate::snapshot::{CampaignProgress, ChainEntry, Difficulty, StateSnapshot};
 use rand::Rng;
 use serde::{Deserialize, Serialize};
-use serde_json::json;
 use std::collections::HashMap;
-use std::fs;
+use std::fmt;
 use std::net::SocketAddr;
+use std::path::Path;
 use std::sync::Arc;
 use tokio::sync::RwLock;
 use tracing::info;
@@ -11,6 +16,45 @@ use tracing::info;
 /// Shared game alias used by the networking layer
 pub type SharedGame = Arc<RwLock<GameState>>;
 
+/// Marble color, serialized by name (`"red"`, ...) for JSON clients. The
+/// discriminant is its wire id in binary snapshots.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MarbleColor {
+    Red,
+    Green,
+    Blue,
+    Yellow,
+    Purple,
+}
+
+impl MarbleColor {
+    /// Full palette, in wire id order.
+    pub const ALL: [MarbleColor; 5] = [
+        MarbleColor::Red,
+        MarbleColor::Green,
+        MarbleColor::Blue,
+        MarbleColor::Yellow,
+        MarbleColor::Purple,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            MarbleColor::Red => "red",
+            MarbleColor::Green => "green",
+            MarbleColor::Blue => "blue",
+            MarbleColor::Yellow => "yellow",
+            MarbleColor::Purple => "purple",
+        }
+    }
+}
+
+impl fmt::Display for MarbleColor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Player {
     pub id: u64,
@@ -18,8 +62,8 @@ pub struct Player {
     pub y: f32,
     pub z: f32,
     pub yaw: f32,
-    pub loaded_color: String,
-    pub next_color: String,
+    pub loaded_color: MarbleColor,
+    pub next_color: MarbleColor,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -32,7 +76,7 @@ pub struct Marble {
     pub vy: f32,
     pub vz: f32,
     pub life: f32,
-    pub color: String,
+    pub color: MarbleColor,
     pub owner: Option<u64>,
 }
 
@@ -41,39 +85,112 @@ pub struct Marble {
 #[derive(Debug, Clone)]
 pub struct ChainMarble {
     pub id: Option<u64>,
-    pub s: f32,                // fraction [0..1] along path
-    pub color: Option<String>, // None => gap
-    pub frozen: bool,          // true if disconnected from spawn point
+    pub s: f32,                     // fraction [0..1] along path
+    pub color: Option<MarbleColor>, // None => gap
+    pub frozen: bool,               // true if disconnected from spawn point
+    pub power: Option<PowerUp>,     // effect triggered when popped in a match
+}
+
+/// Special chain marble effects, triggered when the marble is popped in a match.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PowerUp {
+    /// Chain moves at `SLOW_FACTOR` speed for `SLOW_DURATION` seconds.
+    SlowDown,
+    /// Active segment rolls back toward the spawn for `REVERSE_DURATION` seconds.
+    Reverse,
+    /// Destroys chain marbles within `BOMB_RADIUS` of the popped run.
+    Bomb,
+    /// Destroys every chain marble of the popped color.
+    ColorBlast,
+}
+
+impl PowerUp {
+    pub const ALL: [PowerUp; 4] = [
+        PowerUp::SlowDown,
+        PowerUp::Reverse,
+        PowerUp::Bomb,
+        PowerUp::ColorBlast,
+    ];
+}
+
+// power-up tuning
+const SLOW_DURATION: f32 = 5.0;
+const SLOW_FACTOR: f32 = 0.35;
+const REVERSE_DURATION: f32 = 2.0;
+const REVERSE_FACTOR: f32 = 1.5; // backward speed relative to chain_speed
+const BOMB_RADIUS: f32 = 1.3; // arc length, ~3 marbles either side
+
+/// Height difference, beyond the two marble radii, at which a shot still hits a
+/// chain marble it overlaps in the ground plane. Absorbs path sampling error so
+/// flat shots reach marbles on gentle slopes without hitting raised sections.
+const SHOT_HEIGHT_SLACK: f32 = 0.05;
+/// Rounding allowance (in s) when telling a chain marble's roll from a jump.
+const STEP_SHIFT_SLACK: f32 = 1e-4;
+
+/// Pull speed for levels that don't set `magnet_pull_speed`, in arc length per second.
+pub const DEFAULT_MAGNET_PULL_SPEED: f32 = 1.5;
+
+/// Marbles that may reach the end of the path before the round is lost,
+/// for levels that don't set `end_threshold`.
+pub const DEFAULT_END_THRESHOLD: u32 = 10;
+/// Clear-mode time bonus: points per second the chain was cleared under `par_time`.
+const TIME_BONUS_PER_SEC: f32 = 10.0;
+/// Par time for clear-mode levels that don't set one, in seconds.
+pub const DEFAULT_PAR_TIME: f32 = 120.0;
+
+/// Seconds a player must wait between `swap`s unless the level sets `swap_cooldown`.
+const SWAP_COOLDOWN: f32 = 0.25;
+
+/// Longest lobby name kept from `join_room`, in characters.
+const MAX_NAME_LEN: usize = 24;
+
+/// Seconds between enough players being present and the chain starting to move.
+pub const COUNTDOWN_SECS: f32 = 3.0;
+
+/// Where a room's match is in its lifecycle; only `Playing` advances the chain.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GamePhase {
+    /// No players yet (or the last one left before the match started).
+    Waiting,
+    /// Players present; the match starts when `countdown` reaches 0.
+    Countdown,
+    Playing,
+    /// Every player disconnected mid-match; resumes via a countdown on rejoin.
+    Paused,
+    /// Game over; stays here until `reset`.
+    Finished,
 }
 
 #[derive(Debug)]
 pub struct GameState {
     pub players: HashMap<SocketAddr, Player>,
     pub marbles: Vec<Marble>,
-    pub chain: Vec<ChainMarble>,
+    // one chain per level path
+    pub tracks: Vec<Track>,
 
     pub current_score: u32,
 
-    // game over condition: how many chain marbles have reached/passed the end
-    pub marbles_reached_end: u32,
-    pub game_over: bool,
-
-    // sampled path representation
-    pub path_points: Vec<(f32, f32)>, // control description (for debug)
-    pub samples: Vec<(f32, f32)>,     // dense samples along path in (x,z)
-    pub cum_lengths: Vec<f32>,        // cumulative lengths at sample indices (starts at 0)
-    pub total_length: f32,            // total arc length
+    // lifecycle phase and seconds left while in `GamePhase::Countdown`
+    pub phase: GamePhase,
+    pub countdown: f32,
 
     // level-driven player spawns (loaded from paths/*.json)
     pub spawn_points: Vec<SpawnPoint>,
 
+    // level scenery that stops or deflects shots
+    pub colliders: Vec<Collider>,
+
     // tuning
-    pub spawn_accum: f32,
     pub spawn_interval: f32,
     pub marble_diameter: f32,
     pub spacing_length: f32,
     pub chain_speed: f32, // fraction of total per second
 
+    // level-defined palette, initial length, spawn curve and repeat bias
+    pub chain_rules: ChainRules,
+
     // difficulty scaling
     pub elapsed_time: f32,       // seconds since game start
     pub base_chain_speed: f32,   // starting speed
@@ -83,9 +200,69 @@ pub struct GameState {
     pub next_player_id: u64,
     pub next_marble_id: u64,
 
+    // fixed simulation steps taken since the room was created
+    pub tick: u64,
+
+    // player allowed to start the match (room creator, else first to join)
+    pub host: Option<u64>,
+
+    // power-ups: chance a spawned chain marble carries one, and active effect timers
+    // (slow-down and reverse act on every track)
+    pub power_up_chance: f32,
+    pub slow_timer: f32,
+    pub reverse_timer: f32,
+
+    // arc length per second a frozen segment is pulled toward a same-colored head
+    pub magnet_pull_speed: f32,
+
+    // round rules: clear mode spawns at most `marble_budget` marbles (endless when
+    // None) and is won once they are all popped; `end_threshold` marbles at the end loses
+    pub marble_budget: Option<u32>,
+    pub marbles_spawned: u32,
+    pub end_threshold: u32,
+    pub par_time: f32,
+    pub outcome: Option<RoundOutcome>,
+    // summary of the round that just finished, sent once by the room
+    pub summary: Option<RoundSummary>,
+
+    // campaign rooms: the levels to play in order (None for a single level)
+    pub campaign: Option<Campaign>,
+
+    // seconds between loaded/next swaps (0 = no cooldown), and when each
+    // player id may swap again, in `elapsed_time`
+    pub swap_cooldown: f32,
+    pub swap_ready_at: HashMap<u64, f32>,
+
+    // events produced since the last `take_events`, broadcast by the room
+    pub events: Vec<GameEvent>,
+
     pub token_map: HashMap<String, PersistentPlayer>,
 }
 
+/// One path of the level and the chain rolling along it.
+#[derive(Debug)]
+pub struct Track {
+    pub path: Arc<SampledPath>,
+    pub chain: Vec<ChainMarble>,
+    // seconds accumulated toward the next spawn at s = 0
+    pub spawn_accum: f32,
+    // chain marbles that rolled off the end of this track
+    pub reached_end: u32,
+    // stretches where the chain is out of sight and can't be hit
+    pub tunnels: Vec<TunnelRange>,
+    // s of each chain marble at the start of the step, for swept shot collision
+    pub step_start: HashMap<u64, f32>,
+}
+
+/// Where a shot first touched a chain marble during a step.
+struct ChainContact {
+    index: usize,
+    // fraction of the leg travelled before contact
+    time: f32,
+    // touched on the side toward the end of the path
+    ahead: bool,
+}
+
 #[derive(Debug, Clone)]
 pub struct PersistentPlayer {
     pub id: u64,
@@ -93,62 +270,117 @@ pub struct PersistentPlayer {
     pub y: f32,
     pub z: f32,
     pub yaw: f32,
-    pub loaded_color: String,
-    pub next_color: String,
+    pub loaded_color: MarbleColor,
+    pub next_color: MarbleColor,
     pub connected: bool,
     pub addr: Option<SocketAddr>,
+    pub name: String,
+    pub ready: bool,
+}
+
+/// How a finished round ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RoundOutcome {
+    /// Clear mode: the whole marble budget was spawned and popped.
+    Victory,
+    /// Too many marbles reached the end of the path.
+    Defeat,
 }
 
+/// End-of-round report, sent once as `round_summary` when the game finishes.
+#[derive(Debug, Clone, Serialize)]
+pub struct RoundSummary {
+    pub outcome: RoundOutcome,
+    /// Final score, time bonus included.
+    pub score: u32,
+    pub time_bonus: u32,
+    /// Seconds of play the round took.
+    pub elapsed_time: f32,
+    pub marbles_reached_end: u32,
+    pub end_threshold: u32,
+    /// Campaign stage this round was played on.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub campaign: Option<CampaignProgress>,
+}
+
+/// One level of a campaign, loaded when the room is created.
+#[derive(Debug, Clone)]
+pub struct CampaignStage {
+    pub id: String,
+    pub level: LevelFile,
+}
+
+/// Ordered levels a campaign room plays through; winning a stage loads the next.
+#[derive(Debug, Clone)]
+pub struct Campaign {
+    pub stages: Vec<CampaignStage>,
+    pub stage: usize,
+    /// Score when the current stage started; restarting the stage goes back to it.
+    pub stage_start_score: u32,
+}
+
+/// A connected player as listed in `room_state`.
 #[derive(Debug, Clone, Serialize, Deserialize)]
-struct SpawnPoint {
-    x: f32,
-    y: f32,
-    z: f32,
+pub struct LobbyPlayer {
+    pub id: u64,
+    pub name: String,
+    pub ready: bool,
 }
 
-#[derive(Serialize, Deserialize)]
-struct ChainPath {
-    name: String,
-    points: Vec<(f32, f32, f32)>,
-    #[serde(default)]
-    spawn_points: Vec<SpawnPoint>,
+/// Why `start_match` was refused.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StartMatchError {
+    NotHost,
+    AlreadyStarted,
+    NotReady,
 }
 
-impl Default for GameState {
-    fn default() -> Self {
-        GameState::from_path_json("paths/second-level.json")
-    }
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SpawnPoint {
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
 }
 
 impl GameState {
-    pub fn new() -> Self {
-        Self::default()
-    }
-
     /// Create an instance for a specific level path json (e.g. `paths/first-level.json`).
     /// This resets runtime state (chain, score, timers) while keeping default tuning values.
-    pub fn from_path_json(path_json: &str) -> Self {
+    /// Fails if the level file is missing or describes an unusable path.
+    pub fn from_path_json(path_json: &str) -> Result<Self, LevelError> {
+        Ok(Self::from_level(LevelFile::load(Path::new(path_json))?))
+    }
+
+    /// Create a campaign instance that starts on the first of `stages` (must not be empty).
+    pub fn from_campaign(stages: Vec<CampaignStage>) -> Self {
+        let mut gs = Self::from_level(stages[0].level.clone());
+        gs.campaign = Some(Campaign {
+            stages,
+            stage: 0,
+            stage_start_score: 0,
+        });
+        gs
+    }
+
+    fn from_level(level: LevelFile) -> Self {
         let mut gs = GameState {
             players: HashMap::new(),
             marbles: Vec::new(),
-            chain: Vec::new(),
+            tracks: Vec::new(),
             current_score: 0,
 
-            marbles_reached_end: 0,
-            game_over: false,
-
-            path_points: Vec::new(),
-            samples: Vec::new(),
-            cum_lengths: Vec::new(),
-            total_length: 2.0,
+            phase: GamePhase::Waiting,
+            countdown: 0.0,
 
             spawn_points: Vec::new(),
 
-            spawn_accum: 0.0,
+            colliders: Vec::new(),
+
             spawn_interval: 0.75,
             marble_diameter: 0.4,
             spacing_length: 0.4 * 1.02,
             chain_speed: 0.02,
+            chain_rules: ChainRules::default(),
 
             elapsed_time: 0.0,
             base_chain_speed: 0.02,
@@ -158,11 +390,35 @@ impl GameState {
             next_player_id: 0,
             next_marble_id: 0,
 
+            tick: 0,
+
+            host: None,
+
+            power_up_chance: 0.05,
+            slow_timer: 0.0,
+            reverse_timer: 0.0,
+
+            magnet_pull_speed: DEFAULT_MAGNET_PULL_SPEED,
+
+            marble_budget: None,
+            marbles_spawned: 0,
+            end_threshold: DEFAULT_END_THRESHOLD,
+            par_time: DEFAULT_PAR_TIME,
+            outcome: None,
+            summary: None,
+
+            campaign: None,
+
+            swap_cooldown: SWAP_COOLDOWN,
+            swap_ready_at: HashMap::new(),
+
+            events: Vec::new(),
+
             token_map: HashMap::new(),
         };
 
         // Load selected path
-        gs.read_path(path_json);
+        gs.apply_level(level);
 
         // Reset chain state for the level
         gs.reset_chain();
@@ -170,140 +426,242 @@ impl GameState {
         gs
     }
 
-    fn reset_chain(&mut self) {
-        self.chain.clear();
-        self.marbles.clear();
-        self.current_score = 0;
-        self.marbles_reached_end = 0;
-        self.game_over = false;
-
-        self.spawn_accum = 0.0;
-        self.elapsed_time = 0.0;
-        self.chain_speed = self.base_chain_speed;
-
-        // initial chain
-        let colors = ["red", "green", "blue", "yellow", "purple"];
-        let chain_len = 15usize;
-        let mut rng = rand::thread_rng();
-        for _i in 0..chain_len {
-            let mid = self.next_marble_id;
-            self.next_marble_id += 1;
+    /// Start the level over: fresh chain, score and timers. Players go back to the
+    /// pre-game lobby and ready up again.
+    pub fn reset(&mut self) {
+        // campaigns replay the current stage; a completed campaign starts over
+        let mut first_stage = None;
+        if let Some(campaign) = self.campaign.as_mut() {
+            if self.outcome == Some(RoundOutcome::Victory) && campaign.stage > 0 {
+                campaign.stage = 0;
+                campaign.stage_start_score = 0;
+                first_stage = Some(campaign.stages[0].level.clone());
+            }
+        }
+        if let Some(level) = first_stage {
+            self.apply_level(level);
+            self.respawn_players();
+        }
 
-            let s = 0.0;
-            let color_index = (rng.random::<f32>() * (colors.len() as f32)) as usize;
-            let color = colors[color_index % colors.len()].to_string();
+        self.reset_chain();
+        // a campaign stage keeps the events of the step that won it; a reset drops them
+        self.events.clear();
+        if let Some(campaign) = &self.campaign {
+            self.current_score = campaign.stage_start_score;
+        }
+        self.phase = GamePhase::Waiting;
+        for pp in self.token_map.values_mut() {
+            pp.ready = false;
+        }
+        info!("Game reset, back to lobby");
+    }
 
-            self.chain.push(ChainMarble {
-                id: Some(mid),
-                s,
-                color: Some(color),
-                frozen: false,
-            });
+    /// Host starts the match once every connected player is ready.
+    pub fn start_match(&mut self, addr: &SocketAddr) -> Result<(), StartMatchError> {
+        let id = self.players.get(addr).map(|p| p.id);
+        if id.is_none() || id != self.host {
+            return Err(StartMatchError::NotHost);
+        }
+        if self.phase != GamePhase::Waiting {
+            return Err(StartMatchError::AlreadyStarted);
         }
+        if self.token_map.values().any(|pp| pp.connected && !pp.ready) {
+            return Err(StartMatchError::NotReady);
+        }
+        self.start_countdown();
+        info!("Host {:?} started the match", self.host);
+        Ok(())
     }
 
-    fn prune_gaps(&mut self) {
-        // Drop any gap placeholders; we represent gaps implicitly by s-jumps
-        self.chain.retain(|cm| cm.color.is_some());
+    pub fn set_ready(&mut self, addr: &SocketAddr, ready: bool) {
+        if let Some(pp) = self
+            .token_map
+            .values_mut()
+            .find(|pp| pp.addr == Some(*addr))
+        {
+            pp.ready = ready;
+        }
     }
 
-    fn read_path(&mut self, path_json: &str) {
-        let string_data = fs::read_to_string(path_json).unwrap();
+    /// Connected players with their lobby names and ready flags, by id.
+    pub fn lobby_players(&self) -> Vec<LobbyPlayer> {
+        let mut players: Vec<LobbyPlayer> = self
+            .token_map
+            .values()
+            .filter(|pp| pp.connected)
+            .map(|pp| LobbyPlayer {
+                id: pp.id,
+                name: pp.name.clone(),
+                ready: pp.ready,
+            })
+            .collect();
+        players.sort_by_key(|p| p.id);
+        players
+    }
 
-        let path: ChainPath = serde_json::from_str(&string_data).unwrap();
+    fn start_countdown(&mut self) {
+        self.phase = GamePhase::Countdown;
+        self.countdown = COUNTDOWN_SECS;
+    }
 
-        // Load player spawn points (optional in json)
-        self.spawn_points = path.spawn_points.clone();
+    /// Pause/resume a started match as players come and go. A room that has not
+    /// started stays in the lobby until the host calls `start_match`.
+    fn update_phase_for_players(&mut self) {
+        let present = !self.players.is_empty();
+        match self.phase {
+            GamePhase::Paused if present => self.start_countdown(),
+            GamePhase::Countdown if !present => {
+                // a match that already ran, or a campaign counting into a later
+                // stage, is paused rather than back to waiting
+                let started =
+                    self.elapsed_time > 0.0 || self.campaign.as_ref().is_some_and(|c| c.stage > 0);
+                self.phase = if started {
+                    GamePhase::Paused
+                } else {
+                    GamePhase::Waiting
+                };
+            }
+            GamePhase::Playing if !present => self.phase = GamePhase::Paused,
+            _ => {}
+        }
+    }
 
-        let mut samples: Vec<(f32, f32)> = Vec::new();
+    fn reset_chain(&mut self) {
+        self.marbles.clear();
+        self.current_score = 0;
 
-        for point in path.points {
-            samples.push((point.0, point.2));
+        self.elapsed_time = 0.0;
+        self.swap_ready_at.clear();
+        self.chain_speed = self.base_chain_speed;
+        self.spawn_interval = self.chain_rules.spawn_interval.start;
+        self.outcome = None;
+        self.summary = None;
+        self.slow_timer = 0.0;
+        self.reverse_timer = 0.0;
+
+        // initial chain on every track, as far as the budget allows
+        let mut rng = rand::rng();
+        self.marbles_spawned = 0;
+        for track in self.tracks.iter_mut() {
+            track.chain.clear();
+            track.spawn_accum = 0.0;
+            track.reached_end = 0;
+
+            let initial = self
+                .marble_budget
+                .map_or(self.chain_rules.initial_length, |b| {
+                    let left = b.saturating_sub(self.marbles_spawned) as usize;
+                    self.chain_rules.initial_length.min(left)
+                });
+            self.marbles_spawned += initial as u32;
+            for _i in 0..initial {
+                let mid = self.next_marble_id;
+                self.next_marble_id += 1;
+
+                let s = 0.0;
+                let color = random_color_with_rng(&mut rng, &self.chain_rules.palette);
+
+                track.chain.push(ChainMarble {
+                    id: Some(mid),
+                    s,
+                    color: Some(color),
+                    frozen: false,
+                    power: None,
+                });
+            }
         }
+    }
 
-        // build cumulative lengths
-        let mut cum_lengths: Vec<f32> = Vec::with_capacity(samples.len());
-        let mut acc = 0.0_f32;
-        for i in 0..samples.len() {
-            if i == 0 {
-                cum_lengths.push(0.0);
-                continue;
-            }
-            let (ax, az) = samples[i - 1];
-            let (bx, bz) = samples[i];
-            let d = ((bx - ax) * (bx - ax) + (bz - az) * (bz - az)).sqrt();
-            acc += d;
-            cum_lengths.push(acc);
-        }
-        let total_length = if let Some(&last) = cum_lengths.last() {
-            last
-        } else {
-            0.0
-        };
+    fn apply_level(&mut self, level: LevelFile) {
+        // Load player spawn points (optional in json)
+        self.spawn_points = level.spawn_points;
+        self.colliders = level.colliders;
+
+        self.magnet_pull_speed = level
+            .magnet_pull_speed
+            .map_or(DEFAULT_MAGNET_PULL_SPEED, |speed| speed.max(0.0));
+        self.chain_rules = level.chain;
+        self.marble_budget = level.marble_budget;
+        self.end_threshold = level.end_threshold.unwrap_or(DEFAULT_END_THRESHOLD);
+        self.par_time = level.par_time.unwrap_or(DEFAULT_PAR_TIME);
+        self.swap_cooldown = level.swap_cooldown.unwrap_or(SWAP_COOLDOWN);
+
+        self.tracks = level
+            .tracks
+            .into_iter()
+            .map(|t| Track::new(SampledPath::from_points(&t.points), t.tunnels))
+            .collect();
+    }
 
-        // Save
-        self.samples = samples;
-        self.cum_lengths = cum_lengths;
-        self.total_length = if total_length > 0.0 {
-            total_length
-        } else {
-            1.0
-        };
+    /// Sampled paths of every track, in track order.
+    pub fn paths(&self) -> Vec<Arc<SampledPath>> {
+        self.tracks.iter().map(|t| t.path.clone()).collect()
     }
 
-    /// Map arc-fraction s in [0..1] to world x,z by linear interpolation in samples.
-    fn chain_world_pos(&self, s: f32) -> (f32, f32) {
-        if self.samples.is_empty() {
-            return (0.0, 0.0);
-        }
-        let s_clamped = s.clamp(0.0, 1.0);
-        let target = s_clamped * self.total_length;
-        // binary search in cum_lengths
-        let idx = match self
-            .cum_lengths
-            .binary_search_by(|v| v.partial_cmp(&target).unwrap_or(std::cmp::Ordering::Equal))
-        {
-            Ok(i) => i,
-            Err(i) => i,
-        };
-        if idx == 0 {
-            return self.samples[0];
-        } else if idx >= self.samples.len() {
-            return *self.samples.last().unwrap();
-        } else {
-            // interpolate between idx-1 and idx
-            let i1 = idx - 1;
-            let i2 = idx;
-            let l1 = self.cum_lengths[i1];
-            let l2 = self.cum_lengths[i2];
-            let denom = (l2 - l1).max(1e-6);
-            let t = (target - l1) / denom;
-            let (x1, z1) = self.samples[i1];
-            let (x2, z2) = self.samples[i2];
-            let x = x1 * (1.0 - t) + x2 * t;
-            let z = z1 * (1.0 - t) + z2 * t;
-            return (x, z);
-        }
+    /// Most marbles that reached the end of any one track; the round is lost
+    /// once this hits `end_threshold`.
+    pub fn marbles_reached_end(&self) -> u32 {
+        self.tracks.iter().map(|t| t.reached_end).max().unwrap_or(0)
     }
 
-    /// Restore or create a player by token and bind it to addr.
+    /// Restore or create a player by token and bind it to addr. `name` is shown in
+    /// the lobby (defaults to "Player <id>"); the first player becomes host if the
+    /// room has none.
     pub fn join_with_token(
         &mut self,
         token_opt: Option<String>,
         addr: SocketAddr,
+        name: Option<String>,
     ) -> (String, Player) {
+        let name = name
+            .map(|n| n.trim().chars().take(MAX_NAME_LEN).collect::<String>())
+            .filter(|n| !n.is_empty());
+        let joined = self.bind_player(token_opt, addr, name);
+        if self.host.is_none() {
+            self.host = Some(joined.1.id);
+        }
+        self.update_phase_for_players();
+        joined
+    }
+
+    fn bind_player(
+        &mut self,
+        token_opt: Option<String>,
+        addr: SocketAddr,
+        name: Option<String>,
+    ) -> (String, Player) {
+        // a socket that joins again keeps its player, unless it brings another
+        // player's token, in which case the old binding is released first
+        let mut token_opt = token_opt;
+        let bound = self
+            .token_map
+            .iter()
+            .find(|(_, pp)| pp.addr == Some(addr))
+            .map(|(token, _)| token.clone());
+        if let Some(bound) = bound {
+            match &token_opt {
+                Some(token) if *token != bound && self.token_map.contains_key(token) => {
+                    self.disconnect_by_addr(&addr);
+                }
+                _ => token_opt = Some(bound),
+            }
+        }
+
         if let Some(token) = token_opt {
             if let Some(pp) = self.token_map.get_mut(&token) {
                 pp.connected = true;
                 pp.addr = Some(addr);
+                if let Some(name) = name {
+                    pp.name = name;
+                }
                 let player = Player {
                     id: pp.id,
                     x: pp.x,
                     y: pp.y,
                     z: pp.z,
                     yaw: pp.yaw,
-                    loaded_color: pp.loaded_color.clone(),
-                    next_color: pp.next_color.clone(),
+                    loaded_color: pp.loaded_color,
+                    next_color: pp.next_color,
                 };
                 self.players.insert(addr, player.clone());
                 info!("Restored player id={} from token {}", pp.id, token);
@@ -311,7 +669,7 @@ impl GameState {
             }
         }
 
-        let mut rng = rand::thread_rng();
+        let mut rng = rand::rng();
         let id = self.next_player_id;
         self.next_player_id += 1;
 
@@ -337,8 +695,18 @@ impl GameState {
             (x, 0.0, z)
         };
 
-        let loaded = random_color_with_rng(&mut rng);
-        let next = random_color_with_rng(&mut rng);
+        let loaded = random_shot_color(
+            &mut rng,
+            &self.tracks,
+            &self.chain_rules.palette,
+            self.chain_rules.shot_color_bias,
+        );
+        let next = random_shot_color(
+            &mut rng,
+            &self.tracks,
+            &self.chain_rules.palette,
+            self.chain_rules.shot_color_bias,
+        );
         let token = generate_token(&mut rng);
         let persistent = PersistentPlayer {
             id,
@@ -346,10 +714,12 @@ impl GameState {
             y: py,
             z: pz,
             yaw: 0.0,
-            loaded_color: loaded.clone(),
-            next_color: next.clone(),
+            loaded_color: loaded,
+            next_color: next,
             connected: true,
             addr: Some(addr),
+            name: name.unwrap_or_else(|| format!("Player {}", id + 1)),
+            ready: false,
         };
         self.token_map.insert(token.clone(), persistent.clone());
         let player = Player {
@@ -376,14 +746,19 @@ impl GameState {
                     break;
                 }
             }
+            // hand host to the remaining player who first joined the room (lowest id)
+            if self.host == Some(p.id) {
+                self.host = self.players.values().map(|p| p.id).min();
+            }
         }
+        self.update_phase_for_players();
     }
 
     pub fn handle_aim(&mut self, addr: &SocketAddr, yaw: f32) {
         if let Some(p) = self.players.get_mut(addr) {
             p.yaw = yaw;
             for (_token, pp) in self.token_map.iter_mut() {
-                if Some(addr.clone()) == pp.addr {
+                if Some(*addr) == pp.addr {
                     pp.yaw = yaw;
                     break;
                 }
@@ -392,20 +767,28 @@ impl GameState {
     }
 
     pub fn handle_shoot(&mut self, addr: &SocketAddr) -> Option<Marble> {
+        if self.phase != GamePhase::Playing {
+            return None;
+        }
         if let Some(p) = self.players.get_mut(addr) {
             let mid = self.next_marble_id;
             self.next_marble_id += 1;
             let speed = 8.0_f32;
             let vx = yaw_sin(p.yaw) * speed;
             let vz = yaw_cos(p.yaw) * speed;
-            let color = p.loaded_color.clone();
-            p.loaded_color = p.next_color.clone();
-            let mut rng = rand::thread_rng();
-            p.next_color = random_color_with_rng(&mut rng);
+            let color = p.loaded_color;
+            p.loaded_color = p.next_color;
+            let mut rng = rand::rng();
+            p.next_color = random_shot_color(
+                &mut rng,
+                &self.tracks,
+                &self.chain_rules.palette,
+                self.chain_rules.shot_color_bias,
+            );
             for (_token, pp) in self.token_map.iter_mut() {
-                if Some(addr.clone()) == pp.addr {
-                    pp.loaded_color = p.loaded_color.clone();
-                    pp.next_color = p.next_color.clone();
+                if Some(*addr) == pp.addr {
+                    pp.loaded_color = p.loaded_color;
+                    pp.next_color = p.next_color;
                     break;
                 }
             }
@@ -413,7 +796,8 @@ impl GameState {
             let marble = Marble {
                 id: mid,
                 x: p.x,
-                y: p.y + 0.1,
+                // fly at chain height so shots from the ground pass under raised track
+                y: p.y + CHAIN_HEIGHT,
                 z: p.z,
                 vx,
                 vy: 0.0,
@@ -429,303 +813,486 @@ impl GameState {
         }
     }
 
+    /// Exchange the player's loaded and next colors. Returns false if not playing
+    /// or the player is still on swap cooldown.
+    pub fn handle_swap(&mut self, addr: &SocketAddr) -> bool {
+        if self.phase != GamePhase::Playing {
+            return false;
+        }
+        let Some(p) = self.players.get_mut(addr) else {
+            return false;
+        };
+        if let Some(&ready_at) = self.swap_ready_at.get(&p.id) {
+            if self.elapsed_time < ready_at {
+                return false;
+            }
+        }
+        std::mem::swap(&mut p.loaded_color, &mut p.next_color);
+        if self.swap_cooldown > 0.0 {
+            self.swap_ready_at
+                .insert(p.id, self.elapsed_time + self.swap_cooldown);
+        }
+        for (_token, pp) in self.token_map.iter_mut() {
+            if Some(*addr) == pp.addr {
+                pp.loaded_color = p.loaded_color;
+                pp.next_color = p.next_color;
+                break;
+            }
+        }
+        info!(
+            "Player {} swapped to loaded={} next={}",
+            p.id, p.loaded_color, p.next_color
+        );
+        true
+    }
+
+    /// Advance one fixed simulation step of `dt` seconds.
     pub fn update(&mut self, dt: f32) {
-        // stop simulation once game is over (still allows state broadcasts)
-        if self.game_over {
-            return;
+        self.tick += 1;
+
+        // only a running match advances; other phases still allow state broadcasts
+        match self.phase {
+            GamePhase::Countdown => {
+                self.countdown -= dt;
+                if self.countdown <= 0.0 {
+                    self.countdown = 0.0;
+                    self.phase = GamePhase::Playing;
+                    info!("Countdown finished, match started");
+                }
+                return;
+            }
+            GamePhase::Playing => {}
+            GamePhase::Waiting | GamePhase::Paused | GamePhase::Finished => return,
         }
 
         // difficulty scaling: chain speeds up over time (server-authoritative)
         self.elapsed_time += dt.max(0.0);
         self.chain_speed = (self.base_chain_speed + self.speed_ramp_per_sec * self.elapsed_time)
             .min(self.max_chain_speed);
-        self.spawn_interval = (0.75
-            - 0.2 * (self.chain_speed - self.base_chain_speed)
-                / (self.max_chain_speed - self.base_chain_speed))
-            .max(0.3);
-
-        // update free marbles
-        for m in self.marbles.iter_mut() {
-            m.x += m.vx * dt;
-            m.y += m.vy * dt;
-            m.z += m.vz * dt;
-            m.life -= dt;
+        let ramp = (self.chain_speed - self.base_chain_speed)
+            / (self.max_chain_speed - self.base_chain_speed);
+        self.spawn_interval = self.chain_rules.spawn_interval.at(ramp);
+
+        // power-up effect timers
+        self.slow_timer = (self.slow_timer - dt).max(0.0);
+        self.reverse_timer = (self.reverse_timer - dt).max(0.0);
+        let reversing = self.reverse_timer > 0.0;
+
+        // chain motion this step; power-ups slow or reverse it
+        let advance = if reversing {
+            -REVERSE_FACTOR * self.chain_speed * dt
+        } else if self.slow_timer > 0.0 {
+            SLOW_FACTOR * self.chain_speed * dt
+        } else {
+            self.chain_speed * dt
+        };
+        for t in 0..self.tracks.len() {
+            let track = &mut self.tracks[t];
+            track.step_start = track
+                .chain
+                .iter()
+                .filter_map(|cm| Some((cm.id?, cm.s)))
+                .collect();
+            self.update_track(t, dt, reversing, advance);
+        }
+
+        // move free marbles after the chain, so shots are swept against where it went;
+        // a shot that reaches the chain joins it
+        let max_shift = advance.abs() + STEP_SHIFT_SLACK;
+        let mut i = 0usize;
+        while i < self.marbles.len() {
+            if let Some((t, contact)) = self.move_shot(i, dt, max_shift) {
+                let m = self.marbles.swap_remove(i);
+                self.insert_into_chain(m, t, contact.index, contact.ahead);
+                continue;
+            }
+            i += 1;
         }
         // remove dead/out-of-bounds marbles
         self.marbles
             .retain(|m| m.life > 0.0 && m.x.abs() < 200.0 && m.y > -50.0 && m.z.abs() < 200.0);
 
-        // spawn new chain marbles at start (s=0)
-        self.spawn_accum += dt;
-        while self.spawn_accum >= self.spawn_interval {
-            self.spawn_accum -= self.spawn_interval;
-            let mut rng = rand::thread_rng();
-            let color = random_color_chain(&mut rng, &self.chain);
+        // queued shots must still have something to match
+        self.reroll_missing_shot_colors();
+
+        // clear mode: the budget is spawned and nothing is left on any path
+        if self.phase == GamePhase::Playing
+            && self.budget_spent()
+            && self.tracks.iter().all(Track::is_clear)
+        {
+            self.finish_round(RoundOutcome::Victory);
+        }
+    }
+
+    /// Spawn, advance and re-join the chain on track `t` for one step. `advance`
+    /// is how far unfrozen marbles move in s.
+    fn update_track(&mut self, t: usize, dt: f32, reversing: bool, advance: f32) {
+        // spawn new chain marbles at start (s=0); held back while the chain reverses
+        if !reversing {
+            self.tracks[t].spawn_accum += dt;
+        }
+        while self.tracks[t].spawn_accum >= self.spawn_interval {
+            self.tracks[t].spawn_accum -= self.spawn_interval;
+            if self.budget_spent() {
+                self.tracks[t].spawn_accum = 0.0;
+                break;
+            }
+            self.marbles_spawned += 1;
+            let mut rng = rand::rng();
+            let color = random_color_chain(&mut rng, &self.tracks[t].chain, &self.chain_rules);
+            let power = (rng.random::<f32>() < self.power_up_chance)
+                .then(|| PowerUp::ALL[rng.random_range(0..PowerUp::ALL.len())]);
             let id = self.next_marble_id;
             self.next_marble_id += 1;
-            self.chain.push(ChainMarble {
+            self.tracks[t].chain.push(ChainMarble {
                 id: Some(id),
                 s: 0.0,
                 color: Some(color),
                 frozen: false,
+                power,
             });
         }
 
+        let spacing = self.spacing_length;
+        let track = &mut self.tracks[t];
+
         // defensive: prune any gap placeholders so gaps are implicit by s-jumps
-        self.prune_gaps();
+        track.prune_gaps();
 
-        // advance chain by increasing s proportional to fraction speed = chain_speed * dt
-        // only advance non-frozen marbles
-        for cm in self.chain.iter_mut() {
+        // advance chain by increasing s; only non-frozen marbles move
+        for cm in track.chain.iter_mut() {
             if !cm.frozen {
-                cm.s += self.chain_speed * dt;
-            }
-        }
-        // remove those past end (s >= 1.0) and count how many reached the end
-        let before = self.chain.len();
-        self.chain.retain(|cm| cm.s < 1.0);
-        let removed = before.saturating_sub(self.chain.len());
-
-        if removed > 0 {
-            self.marbles_reached_end = self.marbles_reached_end.saturating_add(removed as u32);
-            if self.marbles_reached_end >= 10 {
-                self.game_over = true;
+                cm.s = (cm.s + advance).max(0.0);
             }
         }
+        // remove those past end (s >= 1.0) and count how many reached this track's end
+        let before = track.chain.len();
+        track.chain.retain(|cm| cm.s < 1.0);
+        let removed = before.saturating_sub(track.chain.len());
+        track.reached_end = track.reached_end.saturating_add(removed as u32);
+        let lost = removed > 0 && track.reached_end >= self.end_threshold;
 
         // equalize spacing per contiguous non-gap segments using arc-length (s * total_length)
-        self.equalize_chain_spacing();
+        track.equalize_chain_spacing(spacing);
+
+        // same-colored segment ends attract: frozen segment rolls back toward the head
+        track.attract_matching_segments(dt, self.magnet_pull_speed, spacing);
 
         // check for reconnection between active and frozen segments
         // (this may call equalize_chain_spacing again and modify positions)
-        self.check_reconnection();
+        let junctions = track.check_reconnection(spacing);
 
         // keep chain sorted (must be after reconnection since it may modify positions)
-        self.chain
-            .sort_by(|a, b| a.s.partial_cmp(&b.s).unwrap_or(std::cmp::Ordering::Equal));
+        track.sort_chain();
+
+        // another track may already have ended the round this step
+        if lost && self.phase == GamePhase::Playing {
+            self.finish_round(RoundOutcome::Defeat);
+        }
+
+        // rejoined segments whose ends share a color pop as a combo
+        for (head, tail) in junctions {
+            if let Some(idx) = self.tracks[t].touching_same_color(head, tail, spacing) {
+                info!(
+                    "Segments rejoined on matching colors at track {} index {}",
+                    t, idx
+                );
+                self.remove_matches_with_combo(t, idx, 1);
+            }
+        }
 
         // safety: ensure there is at least one active segment; if not, unfreeze all
-        if !self.chain.is_empty() && !self.chain.iter().any(|cm| cm.color.is_some() && !cm.frozen) {
-            for cm in self.chain.iter_mut() {
+        let track = &mut self.tracks[t];
+        if !track.chain.is_empty()
+            && !track
+                .chain
+                .iter()
+                .any(|cm| cm.color.is_some() && !cm.frozen)
+        {
+            for cm in track.chain.iter_mut() {
                 if cm.color.is_some() {
                     cm.frozen = false;
                 }
             }
-            info!("Safety: no active segment detected — unfroze all marbles");
-            self.equalize_chain_spacing();
+            info!(
+                "Safety: no active segment detected on track {} — unfroze all marbles",
+                t
+            );
+            track.equalize_chain_spacing(spacing);
         }
+    }
 
-        // collision detection & insertion
-        let mut i = 0usize;
-        while i < self.marbles.len() {
-            let m = self.marbles[i].clone();
-            if let Some(coll_idx) = self.find_collision_index(&m) {
-                self.insert_into_chain(m, coll_idx);
-                self.marbles.swap_remove(i);
-                continue;
+    /// Move free marble `i` through one step of `dt` seconds. Its flight is split
+    /// into straight legs at collider bounces; on each leg the earliest contact,
+    /// collider or chain, wins. Returns the chain contact if the shot reached the
+    /// chain, leaving the marble where it touched. `max_shift` is passed on to
+    /// `Track::sweep_collision`.
+    fn move_shot(&mut self, i: usize, dt: f32, max_shift: f32) -> Option<(usize, ChainContact)> {
+        let radius = self.marble_diameter * 0.5;
+        let mut m = self.marbles[i].clone();
+        let mut contact = None;
+        // fraction of the step already flown
+        let mut leg_t = 0.0;
+        // a bouncing shot can hit more than one collider in a step
+        for _ in 0..obstacle::MAX_BOUNCES_PER_STEP {
+            let remaining = dt * (1.0 - leg_t);
+            let from = (m.x, m.y, m.z);
+            let to = (
+                m.x + m.vx * remaining,
+                m.y + m.vy * remaining,
+                m.z + m.vz * remaining,
+            );
+            let wall = obstacle::first_hit(&self.colliders, (from.0, from.2), (to.0, to.2), radius);
+            if let Some((t, c)) = self.find_collision(from, to, leg_t, max_shift) {
+                if wall.is_none_or(|w| c.time <= w.t) {
+                    (m.x, m.y, m.z) = add(from, scale(sub(to, from), c.time));
+                    contact = Some((t, c));
+                    break;
+                }
             }
-            i += 1;
+            let Some(hit) = wall else {
+                (m.x, m.y, m.z) = to;
+                break;
+            };
+            (m.x, m.y, m.z) = add(from, scale(sub(to, from), hit.t));
+            let bounced = !hit.embedded && self.colliders[hit.index].bounces();
+            self.events.push(GameEvent::ObstacleHit {
+                marble: m.id,
+                obstacle: hit.index,
+                x: m.x,
+                z: m.z,
+                bounced,
+            });
+            if !bounced {
+                m.life = 0.0;
+                break;
+            }
+            (m.vx, m.vz) = obstacle::reflect((m.vx, m.vz), hit.normal);
+            leg_t += (1.0 - leg_t) * hit.t;
         }
+        m.life -= dt;
+        self.marbles[i] = m;
+        contact
     }
 
-    /// Re-space contiguous non-gap segments to have equal arc-length spacing anchored at the head of each segment.
-    /// Only equalizes spacing for non-frozen (active) segments.
-    fn equalize_chain_spacing(&mut self) {
-        if self.chain.is_empty() || self.total_length <= 0.0 {
-            return;
-        }
+    /// Earliest chain contact, over every track, of a shot flying from `from` to
+    /// `to` over the last `1 - from_t` of the step.
+    fn find_collision(
+        &self,
+        from: (f32, f32, f32),
+        to: (f32, f32, f32),
+        from_t: f32,
+        max_shift: f32,
+    ) -> Option<(usize, ChainContact)> {
+        // marbles touch when their centers are one diameter apart
+        self.tracks
+            .iter()
+            .enumerate()
+            .filter_map(|(t, track)| {
+                track
+                    .sweep_collision(from, to, from_t, max_shift, self.marble_diameter)
+                    .map(|contact| (t, contact))
+            })
+            .min_by(|a, b| a.1.time.total_cmp(&b.1.time))
+    }
 
-        // sort indices by s ascending (tail->head)
-        let mut order: Vec<usize> = (0..self.chain.len()).collect();
-        order.sort_by(|&a, &b| {
-            self.chain[a]
-                .s
-                .partial_cmp(&self.chain[b].s)
-                .unwrap_or(std::cmp::Ordering::Equal)
-        });
+    /// True once a clear-mode level has spawned its whole marble budget.
+    fn budget_spent(&self) -> bool {
+        self.marble_budget
+            .is_some_and(|budget| self.marbles_spawned >= budget)
+    }
 
-        // find contiguous non-gap segments using ordered indices
-        let mut segments: Vec<Vec<usize>> = Vec::new();
-        let mut cur: Vec<usize> = Vec::new();
-        for &idx in order.iter() {
-            if self.chain[idx].color.is_some() {
-                cur.push(idx);
-            } else {
-                if !cur.is_empty() {
-                    segments.push(cur);
-                    cur = Vec::new();
-                }
+    /// End the round, award the clear-mode time bonus and queue the summary.
+    fn finish_round(&mut self, outcome: RoundOutcome) {
+        let time_bonus = match outcome {
+            RoundOutcome::Victory => {
+                ((self.par_time - self.elapsed_time).max(0.0) * TIME_BONUS_PER_SEC) as u32
             }
+            RoundOutcome::Defeat => 0,
+        };
+        self.current_score = self.current_score.saturating_add(time_bonus);
+        let summary = RoundSummary {
+            outcome,
+            score: self.current_score,
+            time_bonus,
+            elapsed_time: self.elapsed_time,
+            marbles_reached_end: self.marbles_reached_end(),
+            end_threshold: self.end_threshold,
+            campaign: self.campaign_progress(),
+        };
+        info!(
+            "Round over ({:?}) with score {} (time bonus {})",
+            outcome, self.current_score, time_bonus
+        );
+
+        // a won campaign stage moves straight on to the next level
+        if outcome != RoundOutcome::Victory || !self.advance_stage() {
+            self.phase = GamePhase::Finished;
+            self.outcome = Some(outcome);
         }
-        if !cur.is_empty() {
-            segments.push(cur);
-        }
+        self.summary = Some(summary);
+    }
 
-        let spacing = self.spacing_length.max(0.001);
+    /// Load the next campaign stage, keeping the score, and count it in.
+    /// Returns false if this is not a campaign or the last stage was just won.
+    fn advance_stage(&mut self) -> bool {
+        let Some(campaign) = self.campaign.as_mut() else {
+            return false;
+        };
+        if campaign.stage + 1 >= campaign.stages.len() {
+            return false;
+        }
+        campaign.stage += 1;
+        campaign.stage_start_score = self.current_score;
+        let stage = campaign.stages[campaign.stage].clone();
+        let stage_no = campaign.stage + 1;
+        let stages = campaign.stages.len();
+
+        let score = self.current_score;
+        self.apply_level(stage.level);
+        self.reset_chain();
+        self.current_score = score;
+        self.respawn_players();
+        self.start_countdown();
+        info!(
+            "Campaign advanced to stage {}/{} ({}) with score {}",
+            stage_no, stages, stage.id, score
+        );
+        true
+    }
 
-        for seg in segments.into_iter() {
-            // seg: indices in ascending s (tail->head)
+    /// Move every player to their spawn point on the current level.
+    fn respawn_players(&mut self) {
+        if self.spawn_points.is_empty() {
+            return;
+        }
+        let spawn = |points: &[SpawnPoint], id: u64| {
+            let sp = &points[(id as usize) % points.len()];
+            (sp.x, sp.y, sp.z)
+        };
+        for p in self.players.values_mut() {
+            (p.x, p.y, p.z) = spawn(&self.spawn_points, p.id);
+        }
+        for pp in self.token_map.values_mut() {
+            (pp.x, pp.y, pp.z) = spawn(&self.spawn_points, pp.id);
+        }
+    }
 
-            // Check if this segment is frozen - if all marbles are frozen, skip spacing adjustment
-            let all_frozen = seg.iter().all(|&i| self.chain[i].frozen);
-            if all_frozen {
-                continue; // Don't adjust spacing for frozen segments
-            }
+    /// Current stage of a campaign room, as reported in snapshots.
+    pub fn campaign_progress(&self) -> Option<CampaignProgress> {
+        self.campaign.as_ref().map(|c| CampaignProgress {
+            stage: c.stage,
+            stages: c.stages.len(),
+            level: c.stages[c.stage].id.clone(),
+        })
+    }
 
-            // gather current s -> convert to arc length
-            let seg_s: Vec<f32> = seg.iter().map(|&i| self.chain[i].s).collect();
-            if seg_s.is_empty() {
-                continue;
-            }
-            // head s
-            let s_head = *seg_s
-                .iter()
-                .max_by(|a, b| a.partial_cmp(b).unwrap())
-                .unwrap();
-            let L_head = s_head * self.total_length;
-            let m = seg.len();
-            // desired head->tail lengths
-            let mut desired_Ls: Vec<f32> = (0..m).map(|i| L_head - (i as f32) * spacing).collect();
-            // clamp min to 0
-            for d in desired_Ls.iter_mut() {
-                if *d < 0.0 {
-                    *d = 0.0;
-                }
-            }
-            // convert to s (tail->head)
-            desired_Ls.reverse();
-            // assign back s values
-            for (j, &chain_idx) in seg.iter().enumerate() {
-                let L = desired_Ls[j];
-                let s_new = if self.total_length > 0.0 {
-                    (L / self.total_length).clamp(0.0, 1.0)
-                } else {
-                    0.0
-                };
-                self.chain[chain_idx].s = s_new;
-            }
-        }
+    /// Summary of a round that finished since the last call, if any.
+    pub fn take_summary(&mut self) -> Option<RoundSummary> {
+        self.summary.take()
     }
 
-    /// Find nearest non-gap chain marble within collision distance; returns chain index or None.
-    fn find_collision_index(&self, marble: &Marble) -> Option<usize> {
-        if self.chain.is_empty() || self.samples.is_empty() {
-            return None;
+    /// Re-roll loaded/next colors that no longer appear anywhere in the chain.
+    fn reroll_missing_shot_colors(&mut self) {
+        if self.tracks.iter().all(Track::is_clear) {
+            return;
         }
-        let collision_distance = (self.marble_diameter * 1.8).max(0.7);
-        let collision_sq = collision_distance * collision_distance;
-        let mut best: Option<(usize, f32, f32)> = None; // (index, distance, s_value)
-
-        // Find head marble for debugging
-        let max_s = self
-            .chain
-            .iter()
-            .filter(|cm| cm.color.is_some())
-            .map(|cm| cm.s)
-            .max_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal))
-            .unwrap_or(0.0);
+        let present = |color: MarbleColor| {
+            self.tracks
+                .iter()
+                .any(|t| t.chain.iter().any(|cm| cm.color == Some(color)))
+        };
 
-        for (idx, cm) in self.chain.iter().enumerate() {
-            if cm.color.is_none() {
+        let mut rng = rand::rng();
+        let mut changed: Vec<(SocketAddr, MarbleColor, MarbleColor)> = Vec::new();
+        for (addr, p) in self.players.iter() {
+            if present(p.loaded_color) && present(p.next_color) {
                 continue;
             }
-            let (cx, cz) = self.chain_world_pos(cm.s);
-            let dx = marble.x - cx;
-            let dz = marble.z - cz;
-            let d2 = dx * dx + dz * dz;
-            let dist = d2.sqrt();
-
-            // Log if this is the head marble
-            if (cm.s - max_s).abs() < 0.001 {
+            let mut reroll = |color: MarbleColor| {
+                if present(color) {
+                    color
+                } else {
+                    random_shot_color(
+                        &mut rng,
+                        &self.tracks,
+                        &self.chain_rules.palette,
+                        self.chain_rules.shot_color_bias,
+                    )
+                }
+            };
+            let loaded = reroll(p.loaded_color);
+            let next = reroll(p.next_color);
+            changed.push((*addr, loaded, next));
+        }
+
+        for (addr, loaded, next) in changed {
+            if let Some(p) = self.players.get_mut(&addr) {
                 info!(
-                    "HEAD marble check: idx={}, s={:.3}, pos=({:.2},{:.2}), shot_pos=({:.2},{:.2}), dist={:.3}, collision_radius={:.3}",
-                    idx, cm.s, cx, cz, marble.x, marble.z, dist, collision_distance
+                    "Player {} colors {}/{} left the chain, re-rolled to {}/{}",
+                    p.id, p.loaded_color, p.next_color, loaded, next
                 );
+                p.loaded_color = loaded;
+                p.next_color = next;
             }
-
-            if d2 <= collision_sq {
-                match best {
-                    None => best = Some((idx, dist, cm.s)),
-                    Some((_, bd, bs)) => {
-                        // Prefer closer marbles, but if distances are similar (within 0.15),
-                        // prefer marbles with higher s (closer to head)
-                        if dist < bd - 0.15 || (dist < bd + 0.15 && cm.s > bs) {
-                            best = Some((idx, dist, cm.s));
-                        }
-                    }
+            for (_token, pp) in self.token_map.iter_mut() {
+                if Some(addr) == pp.addr {
+                    pp.loaded_color = loaded;
+                    pp.next_color = next;
+                    break;
                 }
             }
         }
-
-        if let Some((idx, dist, s_val)) = best {
-            let has_gap_before = idx > 0 && self.chain[idx - 1].color.is_none();
-            let has_gap_after = idx + 1 < self.chain.len() && self.chain[idx + 1].color.is_none();
-
-            info!(
-                "Collision detected: idx={}, dist={:.3}, s={:.3}, chain_len={}, gap_before={}, gap_after={}",
-                idx, dist, s_val, self.chain.len(), has_gap_before, has_gap_after
-            );
-        }
-        best.map(|(i, _, _)| i)
     }
 
-    fn insert_into_chain(&mut self, marble: Marble, coll_idx: usize) {
+    fn insert_into_chain(&mut self, marble: Marble, t: usize, coll_idx: usize, ahead: bool) {
         let new_id = marble.id;
-        let color = marble.color.clone();
-        let color_str = color.clone(); // Clone for logging
+        let color = marble.color;
 
-        if self.chain.is_empty() {
-            self.chain.push(ChainMarble {
+        if self.tracks[t].chain.is_empty() {
+            self.tracks[t].chain.push(ChainMarble {
                 id: Some(new_id),
                 s: 0.0,
                 color: Some(color),
                 frozen: false,
+                power: None,
             });
-            info!("Inserted first marble id={} color={}", new_id, color_str);
+            info!("Inserted first marble id={} color={}", new_id, color);
             return;
         }
 
         // Don't inherit frozen state - inserted marbles should always be active
-        let cur_s = self.chain[coll_idx].s;
-        let spacing = self.spacing_length / self.total_length.max(0.1); // Convert to s units
-
-        // Determine if we're hitting the very last marble in the chain (true head)
-        let max_s = self
-            .chain
-            .iter()
-            .filter(|cm| cm.color.is_some())
-            .map(|cm| cm.s)
-            .max_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal))
-            .unwrap_or(0.0);
-
-        let is_true_head = (cur_s - max_s).abs() < 0.001;
+        let cur_s = self.tracks[t].chain[coll_idx].s;
+        let spacing = self.spacing_length / self.tracks[t].path.total_length.max(0.1); // Convert to s units
 
-        // If hitting the true head (last marble), insert slightly ahead to become new head
-        // Otherwise insert behind collision point
-        let insert_s = if is_true_head {
-            (cur_s + spacing * 0.1).min(1.0) // Insert slightly ahead to become new head
+        // Go between the hit marble and its neighbour on the side the shot touched;
+        // spacing is equalized below
+        let insert_s = if ahead {
+            (cur_s + spacing * 0.5).min(1.0)
         } else {
-            (cur_s - spacing).max(0.0) // Insert behind
+            (cur_s - spacing * 0.5).max(0.0)
         };
 
         info!(
-            "Inserting marble id={} color={} at s={:.3} (coll_s={:.3}, is_true_head={})",
-            new_id, color_str, insert_s, cur_s, is_true_head
+            "Inserting marble id={} color={} at s={:.3} (coll_s={:.3}, ahead={})",
+            new_id, color, insert_s, cur_s, ahead
         );
 
-        self.chain.push(ChainMarble {
+        self.tracks[t].chain.push(ChainMarble {
             id: Some(new_id),
             s: insert_s,
             color: Some(color),
             frozen: false,
+            power: None,
         });
 
         // Sort by s
-        self.chain
+        self.tracks[t]
+            .chain
             .sort_by(|a, b| a.s.partial_cmp(&b.s).unwrap_or(std::cmp::Ordering::Equal));
 
         // Find where it ended up after sorting
-        let inserted_idx = self
+        let inserted_idx = self.tracks[t]
             .chain
             .iter()
             .position(|c| c.id == Some(new_id))
@@ -734,43 +1301,49 @@ impl GameState {
         info!(
             "After sort, marble at index {} out of {}",
             inserted_idx,
-            self.chain.len()
+            self.tracks[t].chain.len()
         );
 
         // Check if the inserted marble bridges a gap - if so, remove the gap
         // This allows matching across what was previously a gap
-        if let Some(temp_idx) = self.chain.iter().position(|c| c.id == Some(new_id)) {
-            let inserted_color = self.chain[temp_idx].color.clone();
+        if let Some(temp_idx) = self.tracks[t]
+            .chain
+            .iter()
+            .position(|c| c.id == Some(new_id))
+        {
+            let inserted_color = self.tracks[t].chain[temp_idx].color;
 
             // Check for gaps adjacent to the inserted marble and remove them if colors match
             if let Some(ref color) = inserted_color {
                 // Check gap before
-                if temp_idx > 0 && self.chain[temp_idx - 1].color.is_none() {
+                if temp_idx > 0 && self.tracks[t].chain[temp_idx - 1].color.is_none() {
                     // There's a gap before, check if there's a matching color before the gap
                     if temp_idx >= 2 {
-                        if let Some(ref before_color) = self.chain[temp_idx - 2].color {
+                        if let Some(ref before_color) = self.tracks[t].chain[temp_idx - 2].color {
                             if before_color == color {
                                 info!(
                                     "Removing gap at index {} (bridged by inserted marble)",
                                     temp_idx - 1
                                 );
-                                self.chain[temp_idx - 1].color = Some(color.clone());
+                                self.tracks[t].chain[temp_idx - 1].color = Some(*color);
                             }
                         }
                     }
                 }
 
                 // Check gap after
-                if temp_idx + 1 < self.chain.len() && self.chain[temp_idx + 1].color.is_none() {
+                if temp_idx + 1 < self.tracks[t].chain.len()
+                    && self.tracks[t].chain[temp_idx + 1].color.is_none()
+                {
                     // There's a gap after, check if there's a matching color after the gap
-                    if temp_idx + 2 < self.chain.len() {
-                        if let Some(ref after_color) = self.chain[temp_idx + 2].color {
+                    if temp_idx + 2 < self.tracks[t].chain.len() {
+                        if let Some(ref after_color) = self.tracks[t].chain[temp_idx + 2].color {
                             if after_color == color {
                                 info!(
                                     "Removing gap at index {} (bridged by inserted marble)",
                                     temp_idx + 1
                                 );
-                                self.chain[temp_idx + 1].color = Some(color.clone());
+                                self.tracks[t].chain[temp_idx + 1].color = Some(*color);
                             }
                         }
                     }
@@ -779,22 +1352,22 @@ impl GameState {
         }
 
         // Equalize spacing to handle all positioning properly
-        self.equalize_chain_spacing();
+        self.tracks[t].equalize_chain_spacing(self.spacing_length);
 
         // Clean up all isolated gaps (gaps with no marbles adjacent or at edges)
         // These gaps from previous matches mess up spacing and collision
         let mut i = 0;
-        while i < self.chain.len() {
-            if self.chain[i].color.is_none() {
+        while i < self.tracks[t].chain.len() {
+            if self.tracks[t].chain[i].color.is_none() {
                 // Check if this gap is isolated
-                let has_marble_before = i > 0 && self.chain[i - 1].color.is_some();
-                let has_marble_after =
-                    i + 1 < self.chain.len() && self.chain[i + 1].color.is_some();
+                let has_marble_before = i > 0 && self.tracks[t].chain[i - 1].color.is_some();
+                let has_marble_after = i + 1 < self.tracks[t].chain.len()
+                    && self.tracks[t].chain[i + 1].color.is_some();
 
                 // Remove if isolated (no marbles on both sides, or at edge)
                 if !has_marble_before || !has_marble_after {
                     info!("Removing isolated gap at index {}", i);
-                    self.chain.remove(i);
+                    self.tracks[t].chain.remove(i);
                     // Don't increment i, check same position again
                     continue;
                 }
@@ -804,14 +1377,18 @@ impl GameState {
 
         // After spacing equalization, scan a wider area for matches
         // The inserted marble might have merged with an existing bundle
-        if let Some(final_idx) = self.chain.iter().position(|c| c.id == Some(new_id)) {
+        if let Some(final_idx) = self.tracks[t]
+            .chain
+            .iter()
+            .position(|c| c.id == Some(new_id))
+        {
             info!(
                 "After equalize_chain_spacing, marble is now at index {}",
                 final_idx
             );
 
             // Log the entire chain state for debugging
-            let chain_debug: Vec<String> = self
+            let chain_debug: Vec<String> = self.tracks[t]
                 .chain
                 .iter()
                 .enumerate()
@@ -830,13 +1407,13 @@ impl GameState {
             info!("Chain state: {}", chain_debug.join(" "));
 
             // Find the entire contiguous segment of same-colored marbles around insertion
-            let inserted_color = self.chain[final_idx].color.clone();
+            let inserted_color = self.tracks[t].chain[final_idx].color;
             if let Some(ref color) = inserted_color {
                 // Scan left to find the start of the color group
                 // Skip over single gaps to find matching colors (important for matches across gaps)
                 let mut scan_start = final_idx;
                 while scan_start > 0 {
-                    if let Some(ref c) = self.chain[scan_start - 1].color {
+                    if let Some(ref c) = self.tracks[t].chain[scan_start - 1].color {
                         if c == color {
                             scan_start -= 1;
                         } else {
@@ -845,7 +1422,7 @@ impl GameState {
                     } else {
                         // Hit a gap - check if there's a matching color before it
                         if scan_start >= 2 {
-                            if let Some(ref c) = self.chain[scan_start - 2].color {
+                            if let Some(ref c) = self.tracks[t].chain[scan_start - 2].color {
                                 if c == color {
                                     info!(
                                         "Skipping gap at index {} to continue color group (left scan)",
@@ -863,8 +1440,8 @@ impl GameState {
                 // Scan right to find the end of the color group
                 // Skip over single gaps to find matching colors (important for head matches)
                 let mut scan_end = final_idx;
-                while scan_end + 1 < self.chain.len() {
-                    if let Some(ref c) = self.chain[scan_end + 1].color {
+                while scan_end + 1 < self.tracks[t].chain.len() {
+                    if let Some(ref c) = self.tracks[t].chain[scan_end + 1].color {
                         if c == color {
                             scan_end += 1;
                         } else {
@@ -872,8 +1449,8 @@ impl GameState {
                         }
                     } else {
                         // Hit a gap - check if there's a matching color after it
-                        if scan_end + 2 < self.chain.len() {
-                            if let Some(ref c) = self.chain[scan_end + 2].color {
+                        if scan_end + 2 < self.tracks[t].chain.len() {
+                            if let Some(ref c) = self.tracks[t].chain[scan_end + 2].color {
                                 if c == color {
                                     info!(
                                         "Skipping gap at index {} to continue color group",
@@ -894,10 +1471,10 @@ impl GameState {
                     "Checking entire color group from index {} to {} (color={}, size={})",
                     scan_start, scan_end, color, group_size
                 );
-                self.try_remove_matches(scan_start);
+                self.try_remove_matches(t, scan_start);
 
                 // Log if the marble still exists after match attempt
-                let still_exists = self.chain.iter().any(|c| c.id == Some(new_id));
+                let still_exists = self.tracks[t].chain.iter().any(|c| c.id == Some(new_id));
                 info!(
                     "After match check: inserted marble still_exists={}",
                     still_exists
@@ -906,18 +1483,26 @@ impl GameState {
         }
     }
 
-    fn try_remove_matches(&mut self, idx: usize) {
-        if self.chain.is_empty() {
+    fn try_remove_matches(&mut self, t: usize, idx: usize) {
+        self.remove_matches_with_combo(t, idx, 0);
+    }
+
+    /// Pop the 3+ run of one color through `idx` on track `t`, then cascade: if the marbles
+    /// meeting at the closed gap share a color, check them too with `combo + 1`.
+    /// `combo` counts cascades that led here (0 = the shot itself); each level
+    /// raises the score multiplier by one.
+    fn remove_matches_with_combo(&mut self, t: usize, idx: usize, combo: u32) {
+        if self.tracks[t].chain.is_empty() {
             return;
         }
-        let len = self.chain.len();
+        let len = self.tracks[t].chain.len();
         if idx >= len {
             return;
         }
-        if self.chain[idx].color.is_none() {
+        if self.tracks[t].chain[idx].color.is_none() {
             return;
         }
-        let color = self.chain[idx].color.clone().unwrap();
+        let color = self.tracks[t].chain[idx].color.unwrap();
 
         info!("Checking matches for idx={} color={}", idx, color);
 
@@ -926,7 +1511,7 @@ impl GameState {
         let mut cur = idx;
         while cur > 0 {
             let prev = cur - 1;
-            if let Some(ref c) = self.chain[prev].color {
+            if let Some(ref c) = self.tracks[t].chain[prev].color {
                 if c == &color {
                     left += 1;
                     cur = prev;
@@ -943,7 +1528,7 @@ impl GameState {
         cur = idx;
         while cur + 1 < len {
             let nxt = cur + 1;
-            if let Some(ref c) = self.chain[nxt].color {
+            if let Some(ref c) = self.tracks[t].chain[nxt].color {
                 if c == &color {
                     right += 1;
                     cur = nxt;
@@ -962,7 +1547,7 @@ impl GameState {
         );
 
         if total >= 3 {
-            let start = if idx >= left { idx - left } else { 0 };
+            let start = idx.saturating_sub(left);
             let end = (idx + right).min(len - 1);
 
             // Capture boundary s-values BEFORE removal so we can close the "hole" by shifting the head-side backward.
@@ -970,43 +1555,77 @@ impl GameState {
             // In a Zuma game, after a pop, the marbles behind the removed group move backward to fill the gap.
             // Here that's implemented by moving the entire head-side segment backward in `s`.
             let left_neighbor_s = if start > 0 {
-                self.chain
+                self.tracks[t]
+                    .chain
                     .get(start - 1)
                     .and_then(|cm| cm.color.as_ref().map(|_| cm.s))
             } else {
                 None
             };
-            let right_neighbor_s = if end + 1 < self.chain.len() {
-                self.chain
+            let right_neighbor_s = if end + 1 < self.tracks[t].chain.len() {
+                self.tracks[t]
+                    .chain
                     .get(end + 1)
                     .and_then(|cm| cm.color.as_ref().map(|_| cm.s))
             } else {
                 None
             };
 
+            // Power-ups in the run fire once the run is gone
+            let triggered: Vec<(PowerUp, f32)> = self.tracks[t].chain[start..=end]
+                .iter()
+                .filter_map(|cm| cm.power.map(|p| (p, cm.s)))
+                .collect();
+            let removed_ids: Vec<u64> = self.tracks[t].chain[start..=end]
+                .iter()
+                .filter_map(|cm| cm.id)
+                .collect();
+
+            // Marbles that will meet once the hole closes (cascade candidates)
+            let junction = (
+                start
+                    .checked_sub(1)
+                    .and_then(|i| self.tracks[t].chain[i].id),
+                self.tracks[t].chain.get(end + 1).and_then(|cm| cm.id),
+            );
+
             // Remove matched run
             for i in (start..=end).rev() {
-                self.chain.remove(i);
+                self.tracks[t].chain.remove(i);
             }
             info!("MATCH! Removed {} marbles with color={}", total, color);
 
-            // Score: +10 per marble removed (2->20, 3->30, etc.)
-            let gained = (total as u32) * 10;
+            // Score: +10 per marble removed (2->20, 3->30, etc.), times the combo multiplier
+            let multiplier = combo + 1;
+            let gained = (total as u32) * 10 * multiplier;
             self.current_score = self.current_score.saturating_add(gained);
             info!(
-                "SCORE: +{} (removed {} marbles) => current_score={}",
-                gained, total, self.current_score
+                "SCORE: +{} (removed {} marbles, x{}) => current_score={}",
+                gained, total, multiplier, self.current_score
             );
+            if combo > 0 {
+                self.events.push(GameEvent::Combo {
+                    track: t,
+                    level: combo,
+                    multiplier,
+                    color,
+                    removed: removed_ids,
+                    score: gained,
+                });
+            }
 
             // Drop any explicit gap placeholders; gaps are represented via s-jumps.
-            self.prune_gaps();
+            self.tracks[t].prune_gaps();
+
+            // Where the two sides meet once the hole is closed; power-ups in the run fire there
+            let mut meet_s = None;
 
             // If there are marbles on BOTH sides of the removed run, shift the head-side segment backward
             // so it becomes immediately adjacent to the tail-side segment (closing the hole).
             if let (Some(l_s), Some(r_s)) = (left_neighbor_s, right_neighbor_s) {
-                if !self.chain.is_empty() {
+                if !self.tracks[t].chain.is_empty() {
                     // Identify head-side marbles by their pre-removal boundary `s` (>= right neighbor s).
-                    let mut head_side_indices: Vec<usize> = self
+                    let mut head_side_indices: Vec<usize> = self.tracks[t]
                         .chain
                         .iter()
                         .enumerate()
@@ -1020,19 +1639,21 @@ impl GameState {
                         .collect();
 
                     head_side_indices.sort_by(|&a, &b| {
-                        self.chain[a]
+                        self.tracks[t].chain[a]
                             .s
-                            .partial_cmp(&self.chain[b].s)
+                            .partial_cmp(&self.tracks[t].chain[b].s)
                             .unwrap_or(std::cmp::Ordering::Equal)
                     });
 
                     if !head_side_indices.is_empty() {
-                        let spacing_in_s = self.spacing_length / self.total_length.max(0.1);
+                        let spacing_in_s =
+                            self.spacing_length / self.tracks[t].path.total_length.max(0.1);
 
                         // Target: first head-side marble should sit one spacing after the left neighbor.
                         let first_head_idx = head_side_indices[0];
                         let desired_first_s = (l_s + spacing_in_s).min(0.999);
-                        let current_first_s = self.chain[first_head_idx].s;
+                        let current_first_s = self.tracks[t].chain[first_head_idx].s;
+                        meet_s = Some((l_s + desired_first_s.min(current_first_s)) * 0.5);
 
                         // If current is ahead, pull the whole head-side backward by delta.
                         let delta = current_first_s - desired_first_s;
@@ -1046,26 +1667,360 @@ impl GameState {
                             );
 
                             for &i in head_side_indices.iter() {
-                                self.chain[i].s = (self.chain[i].s - delta).max(0.0);
+                                self.tracks[t].chain[i].s =
+                                    (self.tracks[t].chain[i].s - delta).max(0.0);
                             }
                         }
                     }
                 }
             }
 
+            for (power, s) in triggered {
+                self.trigger_power_up(t, power, color, meet_s.unwrap_or(s));
+            }
+
             // After closing the gap, re-equalize spacing for the active segment.
             // (Frozen segments are not equalized by `equalize_chain_spacing()`.)
-            self.equalize_chain_spacing();
+            self.tracks[t].equalize_chain_spacing(self.spacing_length);
 
             // After removal and hole closing, analyze segments and freeze disconnected ones (head-side freezes).
-            self.analyze_and_freeze_segments();
+            self.tracks[t].analyze_and_freeze_segments(self.spacing_length);
+
+            // Chain reaction: the ends that just met may form a new run
+            if let (Some(left), Some(right)) = junction {
+                if let Some(next) =
+                    self.tracks[t].touching_same_color(left, right, self.spacing_length)
+                {
+                    info!("CASCADE level {} at index {}", combo + 1, next);
+                    self.remove_matches_with_combo(t, next, combo + 1);
+                }
+            }
+        }
+    }
+
+    /// Apply a popped power-up marble's effect and report it as an event.
+    /// `s` is where the popped run was on track `t` after its hole closed; bomb and
+    /// color blast only clear that track.
+    fn trigger_power_up(&mut self, t: usize, power: PowerUp, color: MarbleColor, s: f32) {
+        let mut removed: Vec<u64> = Vec::new();
+        let mut duration = None;
+        match power {
+            PowerUp::SlowDown => {
+                self.slow_timer = SLOW_DURATION;
+                duration = Some(SLOW_DURATION);
+            }
+            PowerUp::Reverse => {
+                self.reverse_timer = REVERSE_DURATION;
+                duration = Some(REVERSE_DURATION);
+            }
+            PowerUp::Bomb => {
+                let radius_s = BOMB_RADIUS / self.tracks[t].path.total_length.max(0.1);
+                self.tracks[t].chain.retain(|cm| {
+                    let hit = cm.color.is_some() && (cm.s - s).abs() <= radius_s;
+                    if hit {
+                        removed.extend(cm.id);
+                    }
+                    !hit
+                });
+            }
+            PowerUp::ColorBlast => {
+                self.tracks[t].chain.retain(|cm| {
+                    let hit = cm.color == Some(color);
+                    if hit {
+                        removed.extend(cm.id);
+                    }
+                    !hit
+                });
+            }
+        }
+
+        let gained = (removed.len() as u32) * 10;
+        self.current_score = self.current_score.saturating_add(gained);
+        info!(
+            "POWER-UP {:?} ({}) at s={:.3}: removed {} marbles, +{}",
+            power,
+            color,
+            s,
+            removed.len(),
+            gained
+        );
+
+        self.events.push(GameEvent::PowerUp {
+            track: t,
+            power,
+            color,
+            s,
+            removed,
+            duration,
+        });
+    }
+
+    /// Events produced since the last call, oldest first.
+    pub fn take_events(&mut self) -> Vec<GameEvent> {
+        std::mem::take(&mut self.events)
+    }
+
+    /// Snapshot of the room with every track's chain as path parameters (excluding gaps).
+    /// `seq` is left at 0 for `SnapshotHistory::record` to assign.
+    pub fn snapshot(&self) -> StateSnapshot {
+        let players: Vec<Player> = self.players.values().cloned().collect();
+        let chain: Vec<ChainEntry> = self
+            .tracks
+            .iter()
+            .enumerate()
+            .flat_map(|(t, track)| {
+                track.chain.iter().filter_map(move |cm| {
+                    Some(ChainEntry {
+                        id: cm.id.unwrap_or(0),
+                        track: t,
+                        s: cm.s,
+                        color: cm.color?,
+                        frozen: cm.frozen,
+                        hidden: track.is_hidden(cm.s),
+                        power: cm.power,
+                    })
+                })
+            })
+            .collect();
+        StateSnapshot {
+            seq: 0,
+            tick: self.tick,
+            players,
+            marbles: self.marbles.clone(),
+            chain,
+            score: self.current_score,
+            game_over: self.phase == GamePhase::Finished,
+            outcome: self.outcome,
+            campaign: self.campaign_progress(),
+            phase: self.phase,
+            countdown: self.countdown,
+            marbles_reached_end: self.marbles_reached_end(),
+            difficulty: Difficulty {
+                elapsed_time: self.elapsed_time,
+                chain_speed: self.chain_speed,
+                base_chain_speed: self.base_chain_speed,
+                max_chain_speed: self.max_chain_speed,
+                speed_ramp_per_sec: self.speed_ramp_per_sec,
+            },
+        }
+    }
+}
+
+impl Track {
+    fn new(path: SampledPath, tunnels: Vec<TunnelRange>) -> Self {
+        Self {
+            path: Arc::new(path),
+            chain: Vec::new(),
+            spawn_accum: 0.0,
+            reached_end: 0,
+            tunnels,
+            step_start: HashMap::new(),
+        }
+    }
+
+    /// True when a marble at `s` is inside one of this track's tunnels.
+    fn is_hidden(&self, s: f32) -> bool {
+        self.tunnels.iter().any(|r| r.contains(s))
+    }
+
+    /// True when no marbles are left on this track.
+    fn is_clear(&self) -> bool {
+        self.chain.iter().all(|cm| cm.color.is_none())
+    }
+
+    fn sort_chain(&mut self) {
+        self.chain
+            .sort_by(|a, b| a.s.partial_cmp(&b.s).unwrap_or(std::cmp::Ordering::Equal));
+    }
+
+    fn prune_gaps(&mut self) {
+        // Drop any gap placeholders; we represent gaps implicitly by s-jumps
+        self.chain.retain(|cm| cm.color.is_some());
+    }
+
+    /// Re-space contiguous non-gap segments to have equal arc-length spacing anchored at the head of each segment.
+    /// Only equalizes spacing for non-frozen (active) segments.
+    fn equalize_chain_spacing(&mut self, spacing_length: f32) {
+        if self.chain.is_empty() || self.path.total_length <= 0.0 {
+            return;
+        }
+
+        // sort indices by s ascending (tail->head)
+        let mut order: Vec<usize> = (0..self.chain.len()).collect();
+        order.sort_by(|&a, &b| {
+            self.chain[a]
+                .s
+                .partial_cmp(&self.chain[b].s)
+                .unwrap_or(std::cmp::Ordering::Equal)
+        });
+
+        // find contiguous non-gap segments using ordered indices; gaps are
+        // mostly implicit s-jumps, so a frozen/active boundary also splits
+        let mut segments: Vec<Vec<usize>> = Vec::new();
+        let mut cur: Vec<usize> = Vec::new();
+        for &idx in order.iter() {
+            if self.chain[idx].color.is_some() {
+                if let Some(&prev) = cur.last() {
+                    if self.chain[prev].frozen != self.chain[idx].frozen {
+                        segments.push(std::mem::take(&mut cur));
+                    }
+                }
+                cur.push(idx);
+            } else {
+                if !cur.is_empty() {
+                    segments.push(cur);
+                    cur = Vec::new();
+                }
+            }
+        }
+        if !cur.is_empty() {
+            segments.push(cur);
+        }
+
+        let spacing = spacing_length.max(0.001);
+
+        for seg in segments.into_iter() {
+            // seg: indices in ascending s (tail->head)
+
+            // Check if this segment is frozen - if all marbles are frozen, skip spacing adjustment
+            let all_frozen = seg.iter().all(|&i| self.chain[i].frozen);
+            if all_frozen {
+                continue; // Don't adjust spacing for frozen segments
+            }
+
+            // gather current s -> convert to arc length
+            let seg_s: Vec<f32> = seg.iter().map(|&i| self.chain[i].s).collect();
+            if seg_s.is_empty() {
+                continue;
+            }
+            // head s
+            let s_head = *seg_s
+                .iter()
+                .max_by(|a, b| a.partial_cmp(b).unwrap())
+                .unwrap();
+            let l_head = s_head * self.path.total_length;
+            let m = seg.len();
+            // desired head->tail lengths
+            let mut desired_ls: Vec<f32> = (0..m).map(|i| l_head - (i as f32) * spacing).collect();
+            // clamp min to 0
+            for d in desired_ls.iter_mut() {
+                if *d < 0.0 {
+                    *d = 0.0;
+                }
+            }
+            // convert to s (tail->head)
+            desired_ls.reverse();
+            // assign back s values
+            for (j, &chain_idx) in seg.iter().enumerate() {
+                let l = desired_ls[j];
+                let s_new = if self.path.total_length > 0.0 {
+                    (l / self.path.total_length).clamp(0.0, 1.0)
+                } else {
+                    0.0
+                };
+                self.chain[chain_idx].s = s_new;
+            }
+        }
+    }
+
+    /// Earliest contact of a shot with a visible chain marble during this step.
+    /// The shot flies straight from `from`, `from_t` of the way through the step,
+    /// to `to` at its end; chain marbles move from their `step_start` position,
+    /// so fast shots can't skip past them. Contact is tested in the ground plane,
+    /// and the heights may differ by at most `contact_distance + SHOT_HEIGHT_SLACK`.
+    /// Returns None when the shot never comes within `contact_distance` of the chain.
+    ///
+    /// Marbles that moved more than `max_shift` in s this step jumped (a hole
+    /// closing, magnet pull, an insertion) rather than rolled; they are only
+    /// tested where they ended up, so the jump doesn't become a phantom hit area.
+    fn sweep_collision(
+        &self,
+        from: (f32, f32, f32),
+        to: (f32, f32, f32),
+        from_t: f32,
+        max_shift: f32,
+        contact_distance: f32,
+    ) -> Option<ChainContact> {
+        if self.chain.is_empty() || self.path.is_empty() {
+            return None;
+        }
+        let shot = sub(to, from);
+        let mut best: Option<ChainContact> = None;
+
+        for (idx, cm) in self.chain.iter().enumerate() {
+            let Some(id) = cm.id else {
+                continue;
+            };
+            if cm.color.is_none() || self.is_hidden(cm.s) {
+                continue;
+            }
+            let start_s = self
+                .step_start
+                .get(&id)
+                .copied()
+                .filter(|s0| (cm.s - s0).abs() <= max_shift)
+                .unwrap_or(cm.s);
+            let leg_s = start_s + (cm.s - start_s) * from_t;
+            let c0 = self.path.chain_pos(leg_s);
+            let c1 = self.path.chain_pos(cm.s);
+
+            // shot relative to this marble in the ground plane, both moving
+            // linearly over the leg
+            let flat = |v: Vec3| (v.0, 0.0, v.2);
+            let q0 = flat(sub(from, c0));
+            let dq = flat(sub(shot, sub(c1, c0)));
+            let Some(time) = first_contact(q0, dq, contact_distance) else {
+                continue;
+            };
+            if best.as_ref().is_some_and(|b| b.time <= time) {
+                continue;
+            }
+            let center = add(c0, scale(sub(c1, c0), time));
+            let contact = add(from, scale(shot, time));
+            if (contact.1 - center.1).abs() > contact_distance + SHOT_HEIGHT_SLACK {
+                continue;
+            }
+
+            // which side of the hit marble the shot touched, along the path
+            let s_at = leg_s + (cm.s - leg_s) * time;
+            let ahead = dot(sub(contact, center), self.path.tangent(s_at)) >= 0.0;
+            best = Some(ChainContact {
+                index: idx,
+                time,
+                ahead,
+            });
+        }
+
+        if let Some(c) = &best {
+            info!(
+                "Collision detected: idx={}, s={:.3}, time={:.2}, ahead={}, chain_len={}",
+                c.index,
+                self.chain[c.index].s,
+                c.time,
+                c.ahead,
+                self.chain.len()
+            );
         }
+        best
+    }
+
+    /// Index of chain marble `left` if it and `right` are adjacent, unfrozen and
+    /// the same color, i.e. a gap between them has closed and could form a run.
+    fn touching_same_color(&mut self, left: u64, right: u64, spacing_length: f32) -> Option<usize> {
+        self.chain
+            .sort_by(|a, b| a.s.partial_cmp(&b.s).unwrap_or(std::cmp::Ordering::Equal));
+        let li = self.chain.iter().position(|cm| cm.id == Some(left))?;
+        let ri = self.chain.iter().position(|cm| cm.id == Some(right))?;
+        let (l, r) = (&self.chain[li], &self.chain[ri]);
+        let gap = (r.s - l.s).abs() * self.path.total_length;
+        let touching = gap <= spacing_length * 1.5 && !l.frozen && !r.frozen;
+        (touching && l.color.is_some() && l.color == r.color).then_some(li)
     }
 
     /// After match removal, identify segments and freeze any that are disconnected from spawn (tail).
     /// The segment with lowest s values is connected to spawn and remains active.
     /// All other segments are frozen until the active segment reconnects with them.
-    fn analyze_and_freeze_segments(&mut self) {
+    fn analyze_and_freeze_segments(&mut self, spacing_length: f32) {
         if self.chain.is_empty() {
             return;
         }
@@ -1096,8 +2051,8 @@ impl GameState {
                     let prev_idx = *cur_segment.last().unwrap();
                     let prev_s = self.chain[prev_idx].s;
                     let cur_s = self.chain[idx].s;
-                    let gap_threshold = self.spacing_length * 2.0; // significant gap
-                    let gap_in_abs = (cur_s - prev_s) * self.total_length;
+                    let gap_threshold = spacing_length * 2.0; // significant gap
+                    let gap_in_abs = (cur_s - prev_s) * self.path.total_length;
 
                     if gap_in_abs > gap_threshold {
                         // Large gap - start new segment
@@ -1147,29 +2102,31 @@ impl GameState {
         }
 
         // Check for reconnection: if active segment head is close to frozen segment tail, unfreeze
-        self.check_reconnection();
+        self.check_reconnection(spacing_length);
     }
 
     /// Check if the active (non-frozen) segment has reached a frozen segment and reconnect them.
-    fn check_reconnection(&mut self) {
+    /// Returns the `(active head id, frozen tail id)` pairs that were joined.
+    fn check_reconnection(&mut self, spacing_length: f32) -> Vec<(u64, u64)> {
+        let mut junctions = Vec::new();
         if self.chain.is_empty() {
-            return;
+            return junctions;
         }
 
-        let reconnect_distance = self.spacing_length * 1.5; // threshold for reconnection
+        let reconnect_distance = spacing_length * 1.5; // threshold for reconnection
 
         // Find the head of the active segment (highest s among non-frozen)
-        let active_head_s = self
+        let active_head = self
             .chain
             .iter()
             .filter(|cm| cm.color.is_some() && !cm.frozen)
-            .map(|cm| cm.s)
-            .max_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+            .max_by(|a, b| a.s.partial_cmp(&b.s).unwrap_or(std::cmp::Ordering::Equal));
 
-        if active_head_s.is_none() {
-            return;
-        }
-        let active_head_s = active_head_s.unwrap();
+        let Some(active_head) = active_head else {
+            return junctions;
+        };
+        let active_head_s = active_head.s;
+        let active_head_id = active_head.id;
 
         // Find the tail of the nearest frozen segment (lowest s among frozen marbles > active_head_s)
         let mut frozen_segments_to_unfreeze: Vec<(f32, f32)> = Vec::new(); // (min_s, max_s) of segments to unfreeze
@@ -1186,7 +2143,7 @@ impl GameState {
         });
 
         if frozen_order.is_empty() {
-            return;
+            return junctions;
         }
 
         // Find contiguous frozen segments
@@ -1200,9 +2157,9 @@ impl GameState {
                 let prev_idx = *cur_seg.last().unwrap();
                 let prev_s = self.chain[prev_idx].s;
                 let cur_s = self.chain[idx].s;
-                let gap = (cur_s - prev_s) * self.total_length;
+                let gap = (cur_s - prev_s) * self.path.total_length;
 
-                if gap > self.spacing_length * 2.0 {
+                if gap > spacing_length * 2.0 {
                     frozen_segs.push(cur_seg);
                     cur_seg = Vec::new();
                 }
@@ -1227,10 +2184,18 @@ impl GameState {
                 .unwrap();
 
             // Check if active head has reached this frozen segment's tail
-            let distance_abs = (seg_min_s - active_head_s) * self.total_length;
+            let distance_abs = (seg_min_s - active_head_s) * self.path.total_length;
 
             if distance_abs >= 0.0 && distance_abs <= reconnect_distance {
                 frozen_segments_to_unfreeze.push((seg_min_s, seg_max_s));
+                let tail_id = seg
+                    .iter()
+                    .map(|&i| &self.chain[i])
+                    .min_by(|a, b| a.s.partial_cmp(&b.s).unwrap_or(std::cmp::Ordering::Equal))
+                    .and_then(|cm| cm.id);
+                if let (Some(head), Some(tail)) = (active_head_id, tail_id) {
+                    junctions.push((head, tail));
+                }
                 info!("RECONNECTING! Active head at s={:.3} reached frozen segment (s range: {:.3} - {:.3})",
                       active_head_s, seg_min_s, seg_max_s);
             }
@@ -1242,11 +2207,12 @@ impl GameState {
             reconnection_happened = true;
 
             // First, collect indices of frozen marbles in this segment (before any modifications)
-            let mut frozen_marble_data: Vec<(usize, String)> = Vec::new();
+            let mut frozen_marble_data: Vec<(usize, MarbleColor)> = Vec::new();
             for (idx, cm) in self.chain.iter().enumerate() {
-                if cm.color.is_some() && cm.frozen && cm.s >= min_s - 0.001 && cm.s <= max_s + 0.001
-                {
-                    frozen_marble_data.push((idx, cm.color.clone().unwrap()));
+                if let Some(color) = cm.color {
+                    if cm.frozen && cm.s >= min_s - 0.001 && cm.s <= max_s + 0.001 {
+                        frozen_marble_data.push((idx, color));
+                    }
                 }
             }
 
@@ -1272,7 +2238,7 @@ impl GameState {
 
             // Pull frozen segment marbles to be immediately adjacent to active segment
             // This ensures they're treated as one contiguous segment for matching
-            let spacing_in_s = self.spacing_length / self.total_length.max(0.1);
+            let spacing_in_s = spacing_length / self.path.total_length.max(0.1);
 
             // After removing gaps, find the frozen marbles again by their position range
             // Note: we need to search by the original s range since indices may have shifted
@@ -1322,52 +2288,73 @@ impl GameState {
 
         // After reconnection, re-equalize spacing to properly connect the segments
         if reconnection_happened {
-            self.equalize_chain_spacing();
+            self.equalize_chain_spacing(spacing_length);
         }
+        junctions
     }
 
-    /// Snapshot: convert chain to world positions (excluding gaps) and send path control points for debug.
-    pub fn snapshot(&self) -> String {
-        let players: Vec<Player> = self.players.values().cloned().collect();
-        let mut marbles: Vec<Marble> = self.marbles.clone();
-        for cm in self.chain.iter() {
-            if cm.color.is_none() {
-                continue;
-            }
-            let (x, z) = self.chain_world_pos(cm.s);
-            marbles.push(Marble {
-                id: cm.id.unwrap_or(0),
-                x,
-                y: 0.5,
-                z,
-                vx: 0.0,
-                vy: 0.0,
-                vz: 0.0,
-                life: 9999.0,
-                color: cm.color.clone().unwrap_or_else(|| "unknown".into()),
-                owner: None,
-            });
+    /// Pull the nearest frozen segment back toward the active head while its
+    /// tail color matches the head's, at `pull_speed` (arc length/sec).
+    /// Stops once the two touch; `check_reconnection` then joins them.
+    fn attract_matching_segments(&mut self, dt: f32, pull_speed: f32, spacing_length: f32) {
+        if pull_speed <= 0.0 || self.path.total_length <= 0.0 {
+            return;
+        }
+
+        let Some(head) = self
+            .chain
+            .iter()
+            .filter(|cm| cm.color.is_some() && !cm.frozen)
+            .max_by(|a, b| a.s.partial_cmp(&b.s).unwrap_or(std::cmp::Ordering::Equal))
+        else {
+            return;
+        };
+        let (head_s, head_color) = (head.s, head.color);
+
+        // frozen marbles ahead of the head, nearest first
+        let mut ahead: Vec<usize> = (0..self.chain.len())
+            .filter(|&i| {
+                let cm = &self.chain[i];
+                cm.color.is_some() && cm.frozen && cm.s > head_s
+            })
+            .collect();
+        ahead.sort_by(|&a, &b| {
+            self.chain[a]
+                .s
+                .partial_cmp(&self.chain[b].s)
+                .unwrap_or(std::cmp::Ordering::Equal)
+        });
+        let Some(&tail) = ahead.first() else {
+            return;
+        };
+        if self.chain[tail].color != head_color {
+            return;
         }
-        json!({
-            "type":"state",
-            "players": players,
-            "marbles": marbles,
-            "score": self.current_score,
-            "game_over": self.game_over,
-            "marbles_reached_end": self.marbles_reached_end,
-            "difficulty": {
-                "elapsed_time": self.elapsed_time,
-                "chain_speed": self.chain_speed,
-                "base_chain_speed": self.base_chain_speed,
-                "max_chain_speed": self.max_chain_speed,
-                "speed_ramp_per_sec": self.speed_ramp_per_sec
+
+        // the contiguous frozen segment starting at that tail
+        let gap_limit = spacing_length * 2.0 / self.path.total_length;
+        let mut segment = vec![tail];
+        for w in ahead.windows(2) {
+            if self.chain[w[1]].s - self.chain[w[0]].s > gap_limit {
+                break;
             }
-        })
-        .to_string()
+            segment.push(w[1]);
+        }
+
+        // move back, but not past touching distance from the head
+        let spacing_s = spacing_length / self.path.total_length;
+        let room = (self.chain[tail].s - (head_s + spacing_s)).max(0.0);
+        let shift = (pull_speed * dt / self.path.total_length).min(room);
+        if shift <= 0.0 {
+            return;
+        }
+        for i in segment {
+            self.chain[i].s -= shift;
+        }
     }
 }
 
-/// Helpers
+// Helpers
 
 fn yaw_sin(yaw: f32) -> f32 {
     yaw.sin()
@@ -1376,45 +2363,113 @@ fn yaw_cos(yaw: f32) -> f32 {
     yaw.cos()
 }
 
-fn random_color_with_rng(rng: &mut impl Rng) -> String {
-    let colors = ["red", "green", "blue", "yellow", "purple"];
-    let idx = (rng.random::<f32>() * (colors.len() as f32)) as usize;
-    colors[idx % colors.len()].to_string()
+fn random_color_with_rng(rng: &mut impl Rng, palette: &[MarbleColor]) -> MarbleColor {
+    palette[rng.random_range(0..palette.len())]
 }
 
-fn random_color_chain(rng: &mut impl Rng, chain: &[ChainMarble]) -> String {
-    let colors = ["red", "green", "blue", "yellow", "purple"];
+/// Color for a player's shot queue, drawn only from colors present on `tracks`.
+/// `bias` blends a uniform pick over present colors (0) with one weighted by
+/// their marble counts (1). Falls back to the palette on an empty chain.
+fn random_shot_color(
+    rng: &mut impl Rng,
+    tracks: &[Track],
+    palette: &[MarbleColor],
+    bias: f32,
+) -> MarbleColor {
+    let counts: Vec<(MarbleColor, usize)> = palette
+        .iter()
+        .map(|&c| {
+            let n = tracks
+                .iter()
+                .flat_map(|t| t.chain.iter())
+                .filter(|cm| cm.color == Some(c))
+                .count();
+            (c, n)
+        })
+        .filter(|&(_, n)| n > 0)
+        .collect();
+    if counts.is_empty() {
+        return random_color_with_rng(rng, palette);
+    }
+
+    let bias = bias.clamp(0.0, 1.0);
+    let total: usize = counts.iter().map(|&(_, n)| n).sum();
+    let weight = |n: usize| (1.0 - bias) / counts.len() as f32 + bias * n as f32 / total as f32;
+
+    let mut roll = rng.random::<f32>();
+    for &(color, n) in counts.iter() {
+        roll -= weight(n);
+        if roll < 0.0 {
+            return color;
+        }
+    }
+    counts[counts.len() - 1].0
+}
 
+fn random_color_chain(
+    rng: &mut impl Rng,
+    chain: &[ChainMarble],
+    rules: &ChainRules,
+) -> MarbleColor {
     // If chain is empty or very small, just use random
     if chain.len() < 3 {
-        let idx = (rng.random::<f32>() * (colors.len() as f32)) as usize;
-        return colors[idx % colors.len()].to_string();
+        return random_color_with_rng(rng, &rules.palette);
     }
 
-    // 60% chance to duplicate a color from the last 10 marbles in the chain
-    if rng.random::<f32>() < 0.6 {
-        // Look at the last 10 marbles (or fewer if chain is shorter)
-        let look_back = chain.len().min(10);
+    // Chance to duplicate a color from the last few marbles in the chain
+    if rng.random::<f32>() < rules.repeat.chance {
+        let look_back = chain.len().min(rules.repeat.look_back);
         let recent_marbles = &chain[chain.len() - look_back..];
 
         // Collect colors from recent marbles
-        let recent_colors: Vec<String> = recent_marbles
-            .iter()
-            .filter_map(|m| m.color.clone())
-            .collect();
+        let recent_colors: Vec<MarbleColor> =
+            recent_marbles.iter().filter_map(|m| m.color).collect();
 
         if !recent_colors.is_empty() {
             let idx = (rng.random::<f32>() * (recent_colors.len() as f32)) as usize;
-            return recent_colors[idx % recent_colors.len()].clone();
+            return recent_colors[idx % recent_colors.len()];
         }
     }
 
-    // 40% chance (or fallback): completely random color
-    let idx = (rng.random::<f32>() * (colors.len() as f32)) as usize;
-    colors[idx % colors.len()].to_string()
+    // Otherwise (or fallback): completely random color
+    random_color_with_rng(rng, &rules.palette)
 }
 
 fn generate_token(rng: &mut impl Rng) -> String {
     let n = rng.random::<u128>();
     format!("{:032x}", n)
 }
+
+type Vec3 = (f32, f32, f32);
+
+fn add(a: Vec3, b: Vec3) -> Vec3 {
+    (a.0 + b.0, a.1 + b.1, a.2 + b.2)
+}
+fn sub(a: Vec3, b: Vec3) -> Vec3 {
+    (a.0 - b.0, a.1 - b.1, a.2 - b.2)
+}
+fn scale(a: Vec3, k: f32) -> Vec3 {
+    (a.0 * k, a.1 * k, a.2 * k)
+}
+fn dot(a: Vec3, b: Vec3) -> f32 {
+    a.0 * b.0 + a.1 * b.1 + a.2 * b.2
+}
+
+/// Smallest u in [0, 1] where `q0 + u * dq` is within `radius` of the origin.
+fn first_contact(q0: Vec3, dq: Vec3, radius: f32) -> Option<f32> {
+    let c = dot(q0, q0) - radius * radius;
+    if c <= 0.0 {
+        return Some(0.0);
+    }
+    let a = dot(dq, dq);
+    let b = dot(q0, dq);
+    if a <= f32::EPSILON || b >= 0.0 {
+        return None;
+    }
+    let disc = b * b - a * c;
+    if disc < 0.0 {
+        return None;
+    }
+    let u = (-b - disc.sqrt()) / a;
+    (u <= 1.0).then_some(u)
+}
@@ -0,0 +1,473 @@
+use crate::game::{MarbleColor, SpawnPoint};
+use crate::obstacle::Collider;
+use crate::path::SampledPath;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use tracing::{info, warn};
+
+/// Level used when a client creates a room without picking one.
+pub const DEFAULT_LEVEL: &str = "first-level";
+
+/// On-disk level file (`<paths_dir>/<level>.json`), as exported by `helpers/pathscript.py`
+/// plus optional metadata for the level picker.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LevelFile {
+    pub name: String,
+    /// Path of a single-track level; `load` moves it into `tracks`.
+    #[serde(default)]
+    pub points: Vec<(f32, f32, f32)>,
+    /// Tunnels of a single-track level; `load` moves them into `tracks`.
+    #[serde(default)]
+    pub tunnels: Vec<TunnelRange>,
+    /// Paths of a level with several chains, each ending in its own hole.
+    #[serde(default)]
+    pub tracks: Vec<LevelTrack>,
+    #[serde(default)]
+    pub spawn_points: Vec<SpawnPoint>,
+    /// Pillars and walls that stop or deflect shots.
+    #[serde(default)]
+    pub colliders: Vec<Collider>,
+    #[serde(default)]
+    pub display_name: Option<String>,
+    #[serde(default)]
+    pub difficulty: LevelDifficulty,
+    #[serde(default)]
+    pub recommended_players: Option<usize>,
+    /// Arc length per second a frozen segment is pulled back toward a
+    /// same-colored chain head (0 disables the attraction).
+    #[serde(default)]
+    pub magnet_pull_speed: Option<f32>,
+    /// Clear mode: total marbles the level spawns, initial chain included. The
+    /// round is won once all of them are popped. Endless when absent.
+    #[serde(default)]
+    pub marble_budget: Option<u32>,
+    /// Marbles that may reach the end of the path before the round is lost.
+    #[serde(default)]
+    pub end_threshold: Option<u32>,
+    /// Clear mode: seconds under which a win earns a time bonus.
+    #[serde(default)]
+    pub par_time: Option<f32>,
+    /// Seconds a player must wait between loaded/next swaps (0 = no cooldown).
+    #[serde(default)]
+    pub swap_cooldown: Option<f32>,
+    /// How the chain is generated; every field falls back to the classic rules.
+    #[serde(default)]
+    pub chain: ChainRules,
+}
+
+/// One path of a level; every track runs its own chain.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LevelTrack {
+    pub points: Vec<(f32, f32, f32)>,
+    #[serde(default)]
+    pub tunnels: Vec<TunnelRange>,
+}
+
+/// Stretch of a track where the chain runs out of sight (under a bridge or
+/// through a tunnel), as fractions of the track's length. Marbles inside it
+/// can't be hit.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TunnelRange {
+    pub start: f32,
+    pub end: f32,
+}
+
+impl TunnelRange {
+    pub fn contains(&self, s: f32) -> bool {
+        s >= self.start && s <= self.end
+    }
+}
+
+/// Chain generation rules a level may override (`"chain": { ... }` in the level file).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ChainRules {
+    /// Colors used by this level, each at most once.
+    pub palette: Vec<MarbleColor>,
+    /// Marbles in the chain when the level starts.
+    pub initial_length: usize,
+    pub spawn_interval: SpawnIntervalCurve,
+    pub repeat: ColorRepeat,
+    /// How strongly shooter colors follow chain counts: 0 = any present color
+    /// equally, 1 = proportional to how many marbles of it are in the chain.
+    pub shot_color_bias: f32,
+}
+
+/// Seconds between chain spawns, eased from `start` to `end` as the chain
+/// speeds up to its cap, never below `min`.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SpawnIntervalCurve {
+    pub start: f32,
+    pub end: f32,
+    pub min: f32,
+}
+
+/// Chance a spawned marble copies a color from the last `look_back` chain
+/// marbles instead of a random palette color; higher makes longer runs.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ColorRepeat {
+    pub chance: f32,
+    pub look_back: usize,
+}
+
+impl Default for ChainRules {
+    fn default() -> Self {
+        Self {
+            palette: MarbleColor::ALL.to_vec(),
+            initial_length: 15,
+            spawn_interval: SpawnIntervalCurve::default(),
+            repeat: ColorRepeat::default(),
+            shot_color_bias: 0.5,
+        }
+    }
+}
+
+impl Default for SpawnIntervalCurve {
+    fn default() -> Self {
+        Self {
+            start: 0.75,
+            end: 0.55,
+            min: 0.3,
+        }
+    }
+}
+
+impl Default for ColorRepeat {
+    fn default() -> Self {
+        Self {
+            chance: 0.6,
+            look_back: 10,
+        }
+    }
+}
+
+impl SpawnIntervalCurve {
+    /// Interval at `progress` in [0, 1] from base to max chain speed.
+    pub fn at(&self, progress: f32) -> f32 {
+        // a level without a speed ramp divides by zero; stay at `start`
+        let t = if progress.is_finite() {
+            progress.clamp(0.0, 1.0)
+        } else {
+            0.0
+        };
+        (self.start + (self.end - self.start) * t).max(self.min)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LevelDifficulty {
+    Easy,
+    #[default]
+    Normal,
+    Hard,
+}
+
+/// Spawn points closer than this are treated as the same spot.
+const MIN_SPAWN_SEPARATION: f32 = 0.01;
+
+/// Why a level file could not be loaded.
+#[derive(Debug)]
+pub enum LevelError {
+    Missing {
+        path: PathBuf,
+        source: io::Error,
+    },
+    BadJson {
+        path: PathBuf,
+        source: serde_json::Error,
+    },
+    TooFewPoints {
+        path: PathBuf,
+        track: usize,
+        count: usize,
+    },
+    ZeroLength {
+        path: PathBuf,
+        track: usize,
+    },
+    DegenerateSpawnPoints {
+        path: PathBuf,
+        first: usize,
+        second: usize,
+    },
+    EmptyPalette {
+        path: PathBuf,
+    },
+    DuplicateColor {
+        path: PathBuf,
+        color: MarbleColor,
+    },
+    BadRule {
+        path: PathBuf,
+        rule: &'static str,
+    },
+}
+
+impl fmt::Display for LevelError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LevelError::Missing { path, source } => {
+                write!(f, "failed to read {}: {}", path.display(), source)
+            }
+            LevelError::BadJson { path, source } => {
+                write!(f, "invalid level json in {}: {}", path.display(), source)
+            }
+            LevelError::TooFewPoints { path, track, count } => write!(
+                f,
+                "{} track {} has {} path points, needs at least 2",
+                path.display(),
+                track,
+                count
+            ),
+            LevelError::ZeroLength { path, track } => {
+                write!(
+                    f,
+                    "{} track {} has a zero-length path",
+                    path.display(),
+                    track
+                )
+            }
+            LevelError::DegenerateSpawnPoints {
+                path,
+                first,
+                second,
+            } => write!(
+                f,
+                "{} has overlapping spawn points {} and {}",
+                path.display(),
+                first,
+                second
+            ),
+            LevelError::EmptyPalette { path } => {
+                write!(f, "{} has an empty chain palette", path.display())
+            }
+            LevelError::DuplicateColor { path, color } => write!(
+                f,
+                "{} lists palette color {} more than once",
+                path.display(),
+                color
+            ),
+            LevelError::BadRule { path, rule } => {
+                write!(f, "{} has an invalid {}", path.display(), rule)
+            }
+        }
+    }
+}
+
+impl std::error::Error for LevelError {}
+
+impl LevelFile {
+    /// Read and parse a level file, rejecting paths the simulation can't run on.
+    /// A top-level `points` path becomes the only entry of `tracks`.
+    pub fn load(path: &Path) -> Result<Self, LevelError> {
+        let text = fs::read_to_string(path).map_err(|source| LevelError::Missing {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        let mut level: LevelFile =
+            serde_json::from_str(&text).map_err(|source| LevelError::BadJson {
+                path: path.to_path_buf(),
+                source,
+            })?;
+        if !level.tracks.is_empty() {
+            let stray = if !level.points.is_empty() {
+                Some("track list (both points and tracks are set)")
+            } else if !level.tunnels.is_empty() {
+                Some("track list (tunnels belong to a track)")
+            } else {
+                None
+            };
+            if let Some(rule) = stray {
+                return Err(LevelError::BadRule {
+                    path: path.to_path_buf(),
+                    rule,
+                });
+            }
+        }
+        if level.tracks.is_empty() {
+            level.tracks.push(LevelTrack {
+                points: std::mem::take(&mut level.points),
+                tunnels: std::mem::take(&mut level.tunnels),
+            });
+        }
+        level.validate(path)?;
+        Ok(level)
+    }
+
+    fn validate(&self, path: &Path) -> Result<(), LevelError> {
+        for (track, t) in self.tracks.iter().enumerate() {
+            if t.points.len() < 2 {
+                return Err(LevelError::TooFewPoints {
+                    path: path.to_path_buf(),
+                    track,
+                    count: t.points.len(),
+                });
+            }
+
+            let sampled = SampledPath::from_points(&t.points);
+            if sampled.cum_lengths.last().copied().unwrap_or(0.0) <= f32::EPSILON {
+                return Err(LevelError::ZeroLength {
+                    path: path.to_path_buf(),
+                    track,
+                });
+            }
+
+            let bad_tunnel = t
+                .tunnels
+                .iter()
+                .any(|r| !(0.0 <= r.start && r.start < r.end && r.end <= 1.0));
+            if bad_tunnel {
+                return Err(LevelError::BadRule {
+                    path: path.to_path_buf(),
+                    rule: "tunnel range",
+                });
+            }
+        }
+
+        if !self.colliders.iter().all(Collider::is_valid) {
+            return Err(LevelError::BadRule {
+                path: path.to_path_buf(),
+                rule: "collider",
+            });
+        }
+
+        for (i, a) in self.spawn_points.iter().enumerate() {
+            for (j, b) in self.spawn_points.iter().enumerate().skip(i + 1) {
+                let d = ((a.x - b.x).powi(2) + (a.y - b.y).powi(2) + (a.z - b.z).powi(2)).sqrt();
+                if d < MIN_SPAWN_SEPARATION {
+                    return Err(LevelError::DegenerateSpawnPoints {
+                        path: path.to_path_buf(),
+                        first: i,
+                        second: j,
+                    });
+                }
+            }
+        }
+
+        self.validate_chain(path)
+    }
+
+    fn validate_chain(&self, path: &Path) -> Result<(), LevelError> {
+        let rules = &self.chain;
+        if rules.palette.is_empty() {
+            return Err(LevelError::EmptyPalette {
+                path: path.to_path_buf(),
+            });
+        }
+        for (i, color) in rules.palette.iter().enumerate() {
+            if rules.palette[..i].contains(color) {
+                return Err(LevelError::DuplicateColor {
+                    path: path.to_path_buf(),
+                    color: *color,
+                });
+            }
+        }
+
+        let curve = &rules.spawn_interval;
+        let bad = if !(curve.start > 0.0 && curve.end > 0.0 && curve.min > 0.0) {
+            Some("chain.spawn_interval")
+        } else if !(0.0..=1.0).contains(&rules.repeat.chance) {
+            Some("chain.repeat.chance")
+        } else if !(0.0..=1.0).contains(&rules.shot_color_bias) {
+            Some("chain.shot_color_bias")
+        } else if self.marble_budget == Some(0) {
+            Some("marble_budget")
+        } else if self.end_threshold == Some(0) {
+            Some("end_threshold")
+        } else if self.par_time.is_some_and(|t| t.is_nan() || t < 0.0) {
+            Some("par_time")
+        } else if self.swap_cooldown.is_some_and(|t| t.is_nan() || t < 0.0) {
+            Some("swap_cooldown")
+        } else {
+            None
+        };
+        match bad {
+            Some(rule) => Err(LevelError::BadRule {
+                path: path.to_path_buf(),
+                rule,
+            }),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Metadata for one playable level, as sent in `levels_list`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LevelInfo {
+    /// Name clients pass as `level` in `create_room` (the file stem).
+    pub id: String,
+    pub display_name: String,
+    pub difficulty: LevelDifficulty,
+    pub recommended_players: usize,
+    #[serde(skip)]
+    pub path: PathBuf,
+    /// No `marble_budget`: the level can be lost but never won.
+    #[serde(skip)]
+    pub endless: bool,
+}
+
+/// Levels found in the paths directory at startup, keyed by id.
+#[derive(Debug, Default)]
+pub struct LevelRegistry {
+    levels: BTreeMap<String, LevelInfo>,
+}
+
+impl LevelRegistry {
+    /// Load every valid `*.json` level in `dir`; invalid files are logged and skipped.
+    pub fn scan(dir: &Path) -> Result<Self, String> {
+        let entries = fs::read_dir(dir)
+            .map_err(|e| format!("failed to read levels dir {}: {}", dir.display(), e))?;
+
+        let mut levels = BTreeMap::new();
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.extension().and_then(|e| e.to_str()) != Some("json") {
+                continue;
+            }
+            let Some(id) = path
+                .file_stem()
+                .and_then(|s| s.to_str())
+                .map(str::to_string)
+            else {
+                continue;
+            };
+
+            match LevelFile::load(&path) {
+                Ok(level) => {
+                    let info = LevelInfo {
+                        display_name: level.display_name.unwrap_or_else(|| id.clone()),
+                        difficulty: level.difficulty,
+                        recommended_players: level.recommended_players.unwrap_or(2),
+                        endless: level.marble_budget.is_none(),
+                        id: id.clone(),
+                        path,
+                    };
+                    info!("Registered level {} ({})", id, info.display_name);
+                    levels.insert(id, info);
+                }
+                Err(e) => warn!("Skipping level: {}", e),
+            }
+        }
+
+        info!("Found {} levels in {}", levels.len(), dir.display());
+        Ok(Self { levels })
+    }
+
+    pub fn get(&self, id: &str) -> Option<&LevelInfo> {
+        self.levels.get(id)
+    }
+
+    /// All levels, ordered by id.
+    pub fn list(&self) -> Vec<LevelInfo> {
+        self.levels.values().cloned().collect()
+    }
+}
@@ -1,10 +1,19 @@
 use std::sync::Arc;
 
+mod config;
+mod events;
 mod game;
+mod level;
 mod network;
+mod obstacle;
+mod path;
+mod protocol;
 mod room;
+mod snapshot;
 
 use axum::{routing::get, Router};
+use config::Config;
+use level::LevelRegistry;
 use room::{RoomManager, SharedRoomManager};
 use tokio::net::TcpListener;
 use tokio::sync::RwLock;
@@ -16,13 +25,26 @@ async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
     // init logging
     tracing_subscriber::fmt::init();
 
+    let config = Config::load()?;
+    info!("Loaded config: {:?}", config);
+
+    // Discover levels
+    let levels = Arc::new(LevelRegistry::scan(&config.paths_dir)?);
+
     // Create room manager
-    let room_manager: SharedRoomManager = Arc::new(RwLock::new(RoomManager::new()));
+    let room_manager: SharedRoomManager = Arc::new(RwLock::new(RoomManager::new(&config, levels)));
 
     // Create a default lobby room
-    {
+    if config.lobby.enabled {
         let mut rm = room_manager.write().await;
-        rm.create_room("Lobby".to_string(), 8, Some("first-level".to_string()));
+        let lobby = &config.lobby;
+        rm.create_room(
+            lobby.name.clone(),
+            lobby.max_players,
+            std::slice::from_ref(&lobby.level),
+            None,
+        )
+        .map_err(|e| format!("failed to create lobby: {}", e))?;
         info!("Default lobby room created");
     }
 
@@ -36,56 +58,10 @@ async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
                 .allow_headers(Any),
         );
 
-    // tick loop for all rooms + broadcast snapshots (20Hz)
-    let rm_tick = room_manager.clone();
-    tokio::spawn(async move {
-        let tick_rate = tokio::time::Duration::from_millis(50); // 20 Hz
-        let mut interval = tokio::time::interval(tick_rate);
-        loop {
-            interval.tick().await;
-
-            // Get all rooms
-            let rooms = {
-                let rm = rm_tick.read().await;
-                rm.list_rooms().await
-            };
-
-            // Update each room
-            for room_info in rooms {
-                let room_lock = {
-                    let rm = rm_tick.read().await;
-                    rm.get_room(&room_info.id)
-                };
-
-                if let Some(room_lock) = room_lock {
-                    let room = room_lock.read().await;
-
-                    // advance game state
-                    {
-                        let mut gs = room.game.write().await;
-                        gs.update(0.05_f32);
-                    }
-
-                    // build snapshot
-                    let payload = {
-                        let gs = room.game.read().await;
-                        gs.snapshot()
-                    };
-
-                    // broadcast to all clients in this room
-                    let clients_map = room.clients.read().await;
-                    for (_addr, tx) in clients_map.iter() {
-                        let _ = tx.send(axum::extract::ws::Message::Text(payload.clone()));
-                    }
-                }
-            }
-        }
-    });
-
-    // Cleanup task - removes empty rooms every 60 seconds
+    // Cleanup task - removes empty rooms every `cleanup_interval_secs` (paused ones after `paused_room_grace_secs`)
     let rm_cleanup = room_manager.clone();
+    let cleanup_interval = config.cleanup_interval();
     tokio::spawn(async move {
-        let cleanup_interval = tokio::time::Duration::from_secs(60);
         let mut interval = tokio::time::interval(cleanup_interval);
         loop {
             interval.tick().await;
@@ -94,7 +70,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
         }
     });
 
-    let addr = "0.0.0.0:8080";
+    let addr = config.bind_addr.as_str();
     let listener = TcpListener::bind(addr).await?;
     info!("HTTP server listening on: {}", addr);
 
@@ -1,5 +1,8 @@
-use crate::game::SharedGame;
-use crate::room::SharedRoomManager;
+use crate::game::{GamePhase, SharedGame, StartMatchError};
+use crate::level::DEFAULT_LEVEL;
+use crate::protocol::{self, ClientMessage, Encoding, ErrorCode, ServerMessage};
+use crate::room::{self, SharedRoomManager};
+use crate::snapshot::{ChainFormat, SnapshotBuilder};
 use axum::{
     extract::{
         ws::{Message, WebSocket, WebSocketUpgrade},
@@ -14,7 +17,16 @@ use std::sync::Arc;
 use tokio::sync::{mpsc, RwLock};
 use tracing::{info, warn};
 
-pub type Clients = Arc<RwLock<HashMap<SocketAddr, mpsc::UnboundedSender<Message>>>>;
+pub type Clients = Arc<RwLock<HashMap<SocketAddr, ClientHandle>>>;
+
+/// Outgoing channel for a connected client plus what it negotiated on join.
+#[derive(Debug)]
+pub struct ClientHandle {
+    pub tx: mpsc::UnboundedSender<Message>,
+    pub encoding: Encoding,
+    pub chain_format: ChainFormat,
+    pub snapshots: SnapshotBuilder,
+}
 
 pub async fn ws_route(
     ws: WebSocketUpgrade,
@@ -43,6 +55,9 @@ async fn handle_socket(socket: WebSocket, addr: SocketAddr, room_manager: Shared
         }
     });
 
+    // Protocol version agreed in the `hello` handshake; `None` until then.
+    let mut protocol_version: Option<u32> = None;
+
     // Main message handling loop
     loop {
         tokio::select! {
@@ -52,196 +67,346 @@ async fn handle_socket(socket: WebSocket, addr: SocketAddr, room_manager: Shared
                     Message::Text(text) => {
                         info!("Received text from {}: {}", addr, text);
 
-                        if let Ok(v) = serde_json::from_str::<serde_json::Value>(&text) {
-                            if let Some(msg_type) = v.get("type").and_then(|t| t.as_str()) {
-                                match msg_type {
-                                    "list_rooms" => {
-                                        // Send list of available rooms
-                                        let rooms = {
-                                            let rm = room_manager.read().await;
-                                            rm.list_rooms().await
+                        let client_msg = match ClientMessage::decode(&text) {
+                            Ok(m) => m,
+                            Err(e) => {
+                                warn!("Rejected message from {}: {}", addr, e.message);
+                                let _ = tx.send(e.to_message().to_ws());
+                                continue;
+                            }
+                        };
+
+                        // Everything except the handshake itself (and heartbeats) requires a negotiated version.
+                        if protocol_version.is_none()
+                            && !matches!(client_msg, ClientMessage::Hello { .. } | ClientMessage::Ping { .. })
+                        {
+                            let error = ServerMessage::error(
+                                ErrorCode::HandshakeRequired,
+                                "Send hello before any other message",
+                            );
+                            let _ = tx.send(error.to_ws());
+                            continue;
+                        }
+
+                        match client_msg {
+                            ClientMessage::Hello { version } => {
+                                match protocol::negotiate_version(version) {
+                                    Some(v) => {
+                                        protocol_version = Some(v);
+                                        let reply = ServerMessage::Hello {
+                                            version: v,
+                                            min_version: protocol::MIN_PROTOCOL_VERSION,
+                                            max_version: protocol::PROTOCOL_VERSION,
                                         };
+                                        let _ = tx.send(reply.to_ws());
+                                        info!("Client {} negotiated protocol v{}", addr, v);
+                                    }
+                                    None => {
+                                        warn!("Client {} requested unsupported protocol v{}", addr, version);
+                                        let error = ServerMessage::error(
+                                            ErrorCode::UnsupportedVersion,
+                                            format!(
+                                                "Protocol v{} is not supported (server accepts v{}..=v{})",
+                                                version,
+                                                protocol::MIN_PROTOCOL_VERSION,
+                                                protocol::PROTOCOL_VERSION
+                                            ),
+                                        );
+                                        let _ = tx.send(error.to_ws());
+                                    }
+                                }
+                            }
 
-                                        let response = serde_json::json!({
-                                            "type": "rooms_list",
-                                            "rooms": rooms,
-                                        });
+                            ClientMessage::ListRooms => {
+                                // Send list of available rooms
+                                let rooms = {
+                                    let rm = room_manager.read().await;
+                                    rm.list_rooms().await
+                                };
+                                let count = rooms.len();
 
-                                        let _ = tx.send(Message::Text(response.to_string()));
-                                        info!("Sent room list to {}: {} rooms", addr, rooms.len());
+                                let _ = tx.send(ServerMessage::RoomsList { rooms }.to_ws());
+                                info!("Sent room list to {}: {} rooms", addr, count);
+                            }
+
+                            ClientMessage::ListLevels => {
+                                let levels = room_manager.read().await.levels();
+                                let _ = tx.send(ServerMessage::LevelsList { levels }.to_ws());
+                            }
+
+                            ClientMessage::CreateRoom { name, max_players, level, campaign } => {
+                                // Create a new room
+                                let name = name.unwrap_or_else(|| "Unnamed Room".to_string());
+                                let max_players = max_players.unwrap_or(2);
+
+                                // Level id from `list_levels`; unknown ids are rejected.
+                                // A campaign plays its levels in order, starting with the first.
+                                let campaign = campaign.filter(|levels| !levels.is_empty());
+                                let levels = match &campaign {
+                                    Some(levels) => levels.clone(),
+                                    None => vec![level.unwrap_or_else(|| DEFAULT_LEVEL.to_string())],
+                                };
+                                let level = levels[0].clone();
+                                // a single level makes a plain room, so don't echo it as a campaign
+                                let campaign = campaign.filter(|levels| levels.len() > 1);
+
+                                // Create room with selected level/path
+                                let created = {
+                                    let mut rm = room_manager.write().await;
+                                    let max_players = rm.clamp_max_players(max_players);
+                                    rm.create_room(name.clone(), max_players, &levels, Some(addr)).map(|room_id| {
+                                        let path_json = rm.level_path(&level).unwrap_or_default();
+                                        (room_id, max_players, path_json)
+                                    })
+                                };
+                                let (room_id, max_players, path_json) = match created {
+                                    Ok(created) => created,
+                                    Err(e) => {
+                                        let _ = tx.send(e.to_message().to_ws());
+                                        continue;
                                     }
+                                };
+
+                                info!("Created room {} for client {} (level={}, path={})", room_id, addr, level, path_json);
+                                let response = ServerMessage::RoomCreated {
+                                    room_id,
+                                    name,
+                                    max_players,
+                                    level,
+                                    path: path_json,
+                                    campaign,
+                                };
+                                let _ = tx.send(response.to_ws());
+                            }
 
-                                    "create_room" => {
-                                        // Create a new room
-                                        let name = v
-                                            .get("name")
-                                            .and_then(|n| n.as_str())
-                                            .unwrap_or("Unnamed Room")
-                                            .to_string();
-
-                                        let max_players =
-                                            v.get("maxPlayers").and_then(|m| m.as_u64()).unwrap_or(2)
-                                                as usize;
-
-                                        // Optional level selection from client UI.
-                                        // Expect "first-level" or "second-level".
-                                        // Map to server path json under `server/paths/`.
-                                        let level = v
-                                            .get("level")
-                                            .and_then(|l| l.as_str())
-                                            .map(|s| s.to_string())
-                                            .unwrap_or_else(|| "first-level".to_string());
-
-                                        let path_json = match level.as_str() {
-                                            "second-level" => "paths/second-level.json".to_string(),
-                                            "first-level" => "paths/first-level.json".to_string(),
-                                            // Fallback: if client sends something unexpected, default safely.
-                                            _ => "paths/first-level.json".to_string(),
-                                        };
+                            ClientMessage::JoinRoom { room_id, token, encoding, name } => {
+                                // Get the room
+                                let room_lock = {
+                                    let rm = room_manager.read().await;
+                                    rm.get_room(&room_id)
+                                };
+
+                                match room_lock {
+                                    Some(room_lock) => {
+                                        let room = room_lock.read().await;
+
+                                        // Check if room is full; a socket already in it keeps its slot
+                                        let bound = room.game.read().await.players.contains_key(&addr);
+                                        if !bound && room.is_full().await {
+                                            warn!("Room {} is full, rejecting {}", room_id, addr);
+                                            let error = ServerMessage::error(ErrorCode::RoomFull, "Room is full");
+                                            let _ = tx.send(error.to_ws());
+                                            continue;
+                                        }
 
-                                        // Create room with selected level/path
-                                        let room_id = {
-                                            let mut rm = room_manager.write().await;
-                                            rm.create_room_with_level(
-                                                name.clone(),
-                                                max_players,
-                                                Some(level.clone()),
-                                                Some(path_json.clone()),
-                                            )
+                                        // Leave the room this socket was in before
+                                        if let (Some(old_id), Some(game), Some(clients)) =
+                                            (&current_room_id, &current_game, &current_clients)
+                                        {
+                                            if *old_id != room_id {
+                                                leave_room(&addr, old_id, game, clients).await;
+                                            }
+                                        }
+
+                                        // Join the game
+                                        let (token, player, paths) = {
+                                            let mut gs = room.game.write().await;
+                                            let (token, player) = gs.join_with_token(token, addr, name);
+                                            if room.creator == Some(addr) {
+                                                gs.host = Some(player.id);
+                                            }
+                                            (token, player, gs.paths())
                                         };
+                                        let chain_format = ChainFormat::for_version(
+                                            protocol_version.unwrap_or(protocol::MIN_PROTOCOL_VERSION),
+                                        );
+
+                                        // Register client in room's client list
+                                        room.clients.write().await.insert(
+                                            addr,
+                                            ClientHandle {
+                                                tx: tx.clone(),
+                                                encoding,
+                                                chain_format,
+                                                snapshots: SnapshotBuilder::default(),
+                                            },
+                                        );
+
+                                        // Track player's room
+                                        {
+                                            let mut rm = room_manager.write().await;
+                                            rm.assign_player_to_room(addr, room_id.clone());
+                                        }
 
-                                        let response = serde_json::json!({
-                                            "type": "room_created",
-                                            "roomId": room_id,
-                                            "name": name,
-                                            "maxPlayers": max_players,
-                                            "level": level,
-                                            "path": path_json,
-                                        });
-
-                                        let _ = tx.send(Message::Text(response.to_string()));
-                                        info!("Created room {} for client {} (level={}, path={})", room_id, addr, level, path_json);
-                                    }
+                                        // Store references for this connection
+                                        current_room_id = Some(room_id.clone());
+                                        current_game = Some(room.game.clone());
+                                        current_clients = Some(room.clients.clone());
 
-                                    "join_room" => {
-                                        // Join a specific room
-                                        let room_id = match v.get("roomId").and_then(|r| r.as_str()) {
-                                            Some(id) => id,
-                                            None => {
-                                                warn!("Client {} sent join_room without roomId", addr);
-                                                let error = serde_json::json!({
-                                                    "type": "error",
-                                                    "message": "Missing roomId",
-                                                });
-                                                let _ = tx.send(Message::Text(error.to_string()));
-                                                continue;
+                                        info!(
+                                            "Player {} joined room {} from {} ({:?} snapshots)",
+                                            player.id, room_id, addr, encoding
+                                        );
+
+                                        // Send welcome message
+                                        let welcome = ServerMessage::Welcome { token, player, room_id, encoding };
+                                        let _ = tx.send(welcome.to_ws());
+
+                                        // Path-param clients place the chain themselves
+                                        if chain_format == ChainFormat::PathParams {
+                                            for (track, path) in paths.iter().enumerate() {
+                                                let _ = tx.send(ServerMessage::path(track, path).to_ws());
                                             }
-                                        };
+                                        }
 
-                                        let token_opt = v
-                                            .get("token")
-                                            .and_then(|t| t.as_str())
-                                            .map(|s| s.to_string());
+                                        room::broadcast_room_state(&room.id, &room.game, &room.clients).await;
+                                    }
+                                    None => {
+                                        warn!("Room {} not found for client {}", room_id, addr);
+                                        let error = ServerMessage::error(ErrorCode::RoomNotFound, "Room not found");
+                                        let _ = tx.send(error.to_ws());
+                                    }
+                                }
+                            }
 
-                                        // Get the room
-                                        let room_lock = {
-                                            let rm = room_manager.read().await;
-                                            rm.get_room(room_id)
-                                        };
+                            ClientMessage::Aim { yaw } => {
+                                // Handle aim update (only if in a room)
+                                match &current_game {
+                                    Some(game) => {
+                                        let mut gs = game.write().await;
+                                        gs.handle_aim(&addr, yaw);
+                                    }
+                                    None => {
+                                        let _ = tx.send(not_in_room().to_ws());
+                                    }
+                                }
+                            }
 
-                                        match room_lock {
-                                            Some(room_lock) => {
-                                                let room = room_lock.read().await;
-
-                                                // Check if room is full
-                                                if room.is_full().await {
-                                                    warn!("Room {} is full, rejecting {}", room_id, addr);
-                                                    let error = serde_json::json!({
-                                                        "type": "error",
-                                                        "message": "Room is full",
-                                                    });
-                                                    let _ = tx.send(Message::Text(error.to_string()));
-                                                    continue;
-                                                }
-
-                                                // Join the game
-                                                let (token, player) = {
-                                                    let mut gs = room.game.write().await;
-                                                    gs.join_with_token(token_opt, addr)
-                                                };
+                            ClientMessage::Shoot => {
+                                // Handle shoot (only if in a room)
+                                match &current_game {
+                                    Some(game) => {
+                                        let mut gs = game.write().await;
+                                        gs.handle_shoot(&addr);
+                                    }
+                                    None => {
+                                        let _ = tx.send(not_in_room().to_ws());
+                                    }
+                                }
+                            }
 
-                                                // Register client in room's client list
-                                                room.clients.write().await.insert(addr, tx.clone());
-
-                                                // Track player's room
-                                                {
-                                                    let mut rm = room_manager.write().await;
-                                                    rm.assign_player_to_room(addr, room_id.to_string());
-                                                }
-
-                                                // Store references for this connection
-                                                current_room_id = Some(room_id.to_string());
-                                                current_game = Some(room.game.clone());
-                                                current_clients = Some(room.clients.clone());
-
-                                                // Send welcome message
-                                                let welcome = serde_json::json!({
-                                                    "type": "welcome",
-                                                    "token": token,
-                                                    "player": player,
-                                                    "roomId": room_id,
-                                                });
-                                                let _ = tx.send(Message::Text(welcome.to_string()));
-
-                                                info!(
-                                                    "Player {} joined room {} from {}",
-                                                    player.id, room_id, addr
-                                                );
+                            ClientMessage::Swap => {
+                                // Handle swap (only if in a room)
+                                match &current_game {
+                                    Some(game) => {
+                                        let mut gs = game.write().await;
+                                        gs.handle_swap(&addr);
+                                    }
+                                    None => {
+                                        let _ = tx.send(not_in_room().to_ws());
+                                    }
+                                }
+                            }
+
+                            ClientMessage::Ready { ready } => {
+                                match (&current_room_id, &current_game, &current_clients) {
+                                    (Some(room_id), Some(game), Some(clients)) => {
+                                        game.write().await.set_ready(&addr, ready);
+                                        room::broadcast_room_state(room_id, game, clients).await;
+                                    }
+                                    _ => {
+                                        let _ = tx.send(not_in_room().to_ws());
+                                    }
+                                }
+                            }
+
+                            ClientMessage::StartMatch => {
+                                match (&current_room_id, &current_game, &current_clients) {
+                                    (Some(room_id), Some(game), Some(clients)) => {
+                                        let started = game.write().await.start_match(&addr);
+                                        match started {
+                                            Ok(()) => {
+                                                info!("Client {} started match in room {}", addr, room_id);
+                                                room::broadcast_room_state(room_id, game, clients).await;
                                             }
-                                            None => {
-                                                warn!("Room {} not found for client {}", room_id, addr);
-                                                let error = serde_json::json!({
-                                                    "type": "error",
-                                                    "message": "Room not found",
-                                                });
-                                                let _ = tx.send(Message::Text(error.to_string()));
+                                            Err(e) => {
+                                                let error = match e {
+                                                    StartMatchError::NotHost => ServerMessage::error(ErrorCode::NotHost, "Only the host can start the match"),
+                                                    StartMatchError::AlreadyStarted => ServerMessage::error(ErrorCode::MatchAlreadyStarted, "Match already started"),
+                                                    StartMatchError::NotReady => ServerMessage::error(ErrorCode::PlayersNotReady, "Not every player is ready"),
+                                                };
+                                                let _ = tx.send(error.to_ws());
                                             }
                                         }
                                     }
+                                    _ => {
+                                        let _ = tx.send(not_in_room().to_ws());
+                                    }
+                                }
+                            }
 
-                                    "aim" => {
-                                        // Handle aim update (only if in a room)
-                                        if let (Some(game), Some(yaw)) =
-                                            (&current_game, v.get("yaw").and_then(|y| y.as_f64()))
-                                        {
+                            ClientMessage::Restart => {
+                                match &current_game {
+                                    Some(game) => {
+                                        let restarted = {
                                             let mut gs = game.write().await;
-                                            gs.handle_aim(&addr, yaw as f32);
+                                            let finished = gs.phase == GamePhase::Finished;
+                                            if finished {
+                                                gs.reset();
+                                            }
+                                            finished
+                                        };
+                                        if restarted {
+                                            info!("Client {} restarted room {:?}", addr, current_room_id);
+                                            if let (Some(room_id), Some(clients)) = (&current_room_id, &current_clients) {
+                                                room::broadcast_room_state(room_id, game, clients).await;
+                                            }
+                                        } else {
+                                            let error = ServerMessage::error(ErrorCode::GameNotFinished, "Game is still in progress");
+                                            let _ = tx.send(error.to_ws());
                                         }
                                     }
+                                    None => {
+                                        let _ = tx.send(not_in_room().to_ws());
+                                    }
+                                }
+                            }
 
-                                    "shoot" => {
-                                        // Handle shoot (only if in a room)
-                                        if let Some(game) = &current_game {
-                                            let mut gs = game.write().await;
-                                            gs.handle_shoot(&addr);
+                            ClientMessage::Ack { seq } => {
+                                match &current_clients {
+                                    Some(clients) => {
+                                        if let Some(client) = clients.write().await.get_mut(&addr) {
+                                            client.snapshots.ack(seq);
                                         }
                                     }
-
-                                    "ping" => {
-                                        // App-level heartbeat ping from client (JSON).
-                                        // Reply with JSON pong so the JS client updates lastPong.
-                                        let pong = serde_json::json!({
-                                            "type": "pong",
-                                            "timestamp": chrono::Utc::now().timestamp_millis(),
-                                        });
-                                        let _ = tx.send(Message::Text(pong.to_string()));
+                                    None => {
+                                        let _ = tx.send(not_in_room().to_ws());
                                     }
+                                }
+                            }
 
-                                    _ => {
-                                        warn!("Unknown message type from {}: {}", addr, msg_type);
+                            ClientMessage::RequestKeyframe => {
+                                match &current_clients {
+                                    Some(clients) => {
+                                        if let Some(client) = clients.write().await.get_mut(&addr) {
+                                            client.snapshots.request_keyframe();
+                                        }
+                                    }
+                                    None => {
+                                        let _ = tx.send(not_in_room().to_ws());
                                     }
                                 }
                             }
+
+                            ClientMessage::Ping { timestamp } => {
+                                // App-level heartbeat ping from client (JSON).
+                                // Reply with JSON pong so the JS client updates lastPong.
+                                let pong = ServerMessage::Pong {
+                                    timestamp: chrono::Utc::now().timestamp_millis(),
+                                    client_timestamp: timestamp,
+                                };
+                                let _ = tx.send(pong.to_ws());
+                            }
                         }
                     }
                     Message::Close(_) => {
@@ -254,6 +419,11 @@ async fn handle_socket(socket: WebSocket, addr: SocketAddr, room_manager: Shared
                     Message::Pong(_) => {}
                     Message::Binary(bin) => {
                         info!("Received {} bytes from {}", bin.len(), addr);
+                        let error = ServerMessage::error(
+                            ErrorCode::UnsupportedMessage,
+                            "Binary client messages are not supported",
+                        );
+                        let _ = tx.send(error.to_ws());
                     }
                 }
             }
@@ -268,15 +438,11 @@ async fn handle_socket(socket: WebSocket, addr: SocketAddr, room_manager: Shared
     // Cleanup on disconnect
     send_task.abort();
 
-    // Remove from room's client list
-    if let Some(clients) = &current_clients {
-        clients.write().await.remove(&addr);
-    }
-
-    // Disconnect from game
-    if let Some(game) = &current_game {
-        let mut gs = game.write().await;
-        gs.disconnect_by_addr(&addr);
+    // Leave the room and tell the rest of it
+    if let (Some(room_id), Some(game), Some(clients)) =
+        (&current_room_id, &current_game, &current_clients)
+    {
+        leave_room(&addr, room_id, game, clients).await;
     }
 
     // Remove player from room manager
@@ -290,3 +456,15 @@ async fn handle_socket(socket: WebSocket, addr: SocketAddr, room_manager: Shared
         addr, current_room_id
     );
 }
+
+/// Take a socket out of a room: drop its client handle, mark its player
+/// disconnected and send the rest of the room its new `room_state`.
+async fn leave_room(addr: &SocketAddr, room_id: &str, game: &SharedGame, clients: &Clients) {
+    clients.write().await.remove(addr);
+    game.write().await.disconnect_by_addr(addr);
+    room::broadcast_room_state(room_id, game, clients).await;
+}
+
+fn not_in_room() -> ServerMessage {
+    ServerMessage::error(ErrorCode::NotInRoom, "Join a room first")
+}
@@ -0,0 +1,232 @@
+use serde::{Deserialize, Serialize};
+
+/// Colliders a bouncing shot may hit in one simulation step; whatever is left
+/// of its move after that is dropped.
+pub const MAX_BOUNCES_PER_STEP: usize = 4;
+
+/// Level scenery that shots can't pass, as a 2D shape in the ground plane
+/// (`(x, z)` pairs). It blocks shots at any height. With `bounce` set, shots
+/// are deflected instead of stopped.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "shape", rename_all = "snake_case")]
+pub enum Collider {
+    /// Pillar.
+    Circle {
+        center: (f32, f32),
+        radius: f32,
+        #[serde(default)]
+        bounce: bool,
+    },
+    /// Wall between two points.
+    Segment {
+        from: (f32, f32),
+        to: (f32, f32),
+        #[serde(default)]
+        bounce: bool,
+    },
+    /// Closed outline; the last point joins back to the first.
+    Polygon {
+        points: Vec<(f32, f32)>,
+        #[serde(default)]
+        bounce: bool,
+    },
+}
+
+/// First contact of a shot with a collider during one move.
+#[derive(Debug, Clone, Copy)]
+pub struct ColliderHit {
+    /// Index into the level's collider list.
+    pub index: usize,
+    /// Fraction of the move travelled before contact, in [0, 1].
+    pub t: f32,
+    /// Unit surface normal at the contact, facing the shot.
+    pub normal: (f32, f32),
+    /// The shot started inside the shape, so there is no surface to bounce off.
+    pub embedded: bool,
+}
+
+impl Collider {
+    pub fn bounces(&self) -> bool {
+        match self {
+            Collider::Circle { bounce, .. }
+            | Collider::Segment { bounce, .. }
+            | Collider::Polygon { bounce, .. } => *bounce,
+        }
+    }
+
+    /// False for shapes that can't be hit: non-finite coordinates, a circle
+    /// without area, a zero-length wall or a polygon with under 3 points.
+    pub fn is_valid(&self) -> bool {
+        let finite = |p: &(f32, f32)| p.0.is_finite() && p.1.is_finite();
+        match self {
+            Collider::Circle { center, radius, .. } => {
+                finite(center) && radius.is_finite() && *radius > 0.0
+            }
+            Collider::Segment { from, to, .. } => finite(from) && finite(to) && from != to,
+            Collider::Polygon { points, .. } => points.len() >= 3 && points.iter().all(finite),
+        }
+    }
+
+    /// True when `p` lies inside the shape (walls have no inside).
+    fn contains(&self, p: (f32, f32)) -> bool {
+        match self {
+            Collider::Circle { center, radius, .. } => {
+                (p.0 - center.0).powi(2) + (p.1 - center.1).powi(2) < radius * radius
+            }
+            Collider::Segment { .. } => false,
+            Collider::Polygon { points, .. } => {
+                // even-odd rule: count edges crossed by a ray toward +x
+                let mut inside = false;
+                for i in 0..points.len() {
+                    let a = points[i];
+                    let b = points[(i + 1) % points.len()];
+                    if (a.1 > p.1) != (b.1 > p.1) {
+                        let x = a.0 + (p.1 - a.1) / (b.1 - a.1) * (b.0 - a.0);
+                        if p.0 < x {
+                            inside = !inside;
+                        }
+                    }
+                }
+                inside
+            }
+        }
+    }
+
+    /// Earliest contact of a circle of `radius` moving from `from` to `to`, as
+    /// `(t, normal)`. A shot already touching the shape only hits it while
+    /// moving further in, so a deflected shot can leave.
+    fn sweep(&self, from: (f32, f32), to: (f32, f32), radius: f32) -> Option<(f32, (f32, f32))> {
+        let d = (to.0 - from.0, to.1 - from.1);
+        match self {
+            Collider::Circle {
+                center, radius: r, ..
+            } => sweep_circle(from, d, *center, r + radius),
+            Collider::Segment { from: a, to: b, .. } => sweep_capsule(from, d, *a, *b, radius),
+            Collider::Polygon { points, .. } => (0..points.len())
+                .filter_map(|i| {
+                    let a = points[i];
+                    let b = points[(i + 1) % points.len()];
+                    sweep_capsule(from, d, a, b, radius)
+                })
+                .min_by(|x, y| x.0.total_cmp(&y.0)),
+        }
+    }
+}
+
+/// Earliest contact among `colliders` for a shot of `radius` moving from
+/// `from` to `to`, or None if the move is clear. A shot starting inside a
+/// shape (e.g. fired by a player standing in it) hits it at once.
+pub fn first_hit(
+    colliders: &[Collider],
+    from: (f32, f32),
+    to: (f32, f32),
+    radius: f32,
+) -> Option<ColliderHit> {
+    colliders
+        .iter()
+        .enumerate()
+        .filter_map(|(index, c)| {
+            if c.contains(from) {
+                return Some(ColliderHit {
+                    index,
+                    t: 0.0,
+                    normal: (0.0, 0.0),
+                    embedded: true,
+                });
+            }
+            let (t, normal) = c.sweep(from, to, radius)?;
+            Some(ColliderHit {
+                index,
+                t,
+                normal,
+                embedded: false,
+            })
+        })
+        .min_by(|a, b| a.t.total_cmp(&b.t))
+}
+
+/// Reflect velocity `v` off a surface with unit normal `n`.
+pub fn reflect(v: (f32, f32), n: (f32, f32)) -> (f32, f32) {
+    let dot = v.0 * n.0 + v.1 * n.1;
+    (v.0 - 2.0 * dot * n.0, v.1 - 2.0 * dot * n.1)
+}
+
+// point p moving by d against a circle of radius r at c
+fn sweep_circle(p: (f32, f32), d: (f32, f32), c: (f32, f32), r: f32) -> Option<(f32, (f32, f32))> {
+    let f = (p.0 - c.0, p.1 - c.1);
+    let a = d.0 * d.0 + d.1 * d.1;
+    let b = f.0 * d.0 + f.1 * d.1;
+    let k = f.0 * f.0 + f.1 * f.1 - r * r;
+    if k <= 0.0 {
+        // already touching: only a move inward counts
+        return (b < 0.0).then(|| (0.0, normalize(f)));
+    }
+    if a <= f32::EPSILON || b >= 0.0 {
+        return None;
+    }
+    let disc = b * b - a * k;
+    if disc < 0.0 {
+        return None;
+    }
+    let t = (-b - disc.sqrt()) / a;
+    if t > 1.0 {
+        return None;
+    }
+    let hit = (f.0 + d.0 * t, f.1 + d.1 * t);
+    Some((t, normalize(hit)))
+}
+
+// point p moving by d against segment a-b thickened by r (flat sides, round ends)
+fn sweep_capsule(
+    p: (f32, f32),
+    d: (f32, f32),
+    a: (f32, f32),
+    b: (f32, f32),
+    r: f32,
+) -> Option<(f32, (f32, f32))> {
+    let e = (b.0 - a.0, b.1 - a.1);
+    let len2 = e.0 * e.0 + e.1 * e.1;
+    if len2 <= f32::EPSILON {
+        return sweep_circle(p, d, a, r);
+    }
+    let len = len2.sqrt();
+    let n = (-e.1 / len, e.0 / len);
+    let along = |q: (f32, f32)| ((q.0 - a.0) * e.0 + (q.1 - a.1) * e.1) / len2;
+
+    // signed distance from the wall's line and how fast it changes
+    let s0 = (p.0 - a.0) * n.0 + (p.1 - a.1) * n.1;
+    let ds = d.0 * n.0 + d.1 * n.1;
+    let side = if s0 < 0.0 { -1.0 } else { 1.0 };
+    let normal = (n.0 * side, n.1 * side);
+
+    let mut best: Option<(f32, (f32, f32))> = None;
+    if s0.abs() < r {
+        if (0.0..=1.0).contains(&along(p)) {
+            // already touching the flat side: only a move inward counts
+            return (ds * side < 0.0).then_some((0.0, normal));
+        }
+    } else if ds * side < 0.0 {
+        let t = (side * r - s0) / ds;
+        let q = (p.0 + d.0 * t, p.1 + d.1 * t);
+        if t <= 1.0 && (0.0..=1.0).contains(&along(q)) {
+            best = Some((t, normal));
+        }
+    }
+    for end in [a, b] {
+        if let Some(hit) = sweep_circle(p, d, end, r) {
+            if best.is_none_or(|b| hit.0 < b.0) {
+                best = Some(hit);
+            }
+        }
+    }
+    best
+}
+
+fn normalize(v: (f32, f32)) -> (f32, f32) {
+    let len = (v.0 * v.0 + v.1 * v.1).sqrt();
+    if len > f32::EPSILON {
+        (v.0 / len, v.1 / len)
+    } else {
+        (0.0, 0.0)
+    }
+}
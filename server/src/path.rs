@@ -0,0 +1,133 @@
+/// Height at which chain marbles ride above the path.
+pub const CHAIN_HEIGHT: f32 = 0.5;
+
+/// A level path resampled for arc-length lookups. Static for the life of a level,
+/// so it is shared (`Arc`) between the simulation and snapshot encoding.
+#[derive(Debug, Clone)]
+pub struct SampledPath {
+    pub samples: Vec<(f32, f32, f32)>, // dense samples along path in (x,y,z)
+    pub cum_lengths: Vec<f32>,         // cumulative 3D lengths at sample indices (starts at 0)
+    pub total_length: f32,             // total arc length
+}
+
+impl SampledPath {
+    /// Build from level points `(x, y, z)`, keeping their elevation.
+    pub fn from_points(points: &[(f32, f32, f32)]) -> Self {
+        let samples: Vec<(f32, f32, f32)> = points.to_vec();
+
+        // build cumulative lengths
+        let mut cum_lengths: Vec<f32> = Vec::with_capacity(samples.len());
+        let mut acc = 0.0_f32;
+        for i in 0..samples.len() {
+            if i == 0 {
+                cum_lengths.push(0.0);
+                continue;
+            }
+            let (ax, ay, az) = samples[i - 1];
+            let (bx, by, bz) = samples[i];
+            let d = ((bx - ax).powi(2) + (by - ay).powi(2) + (bz - az).powi(2)).sqrt();
+            acc += d;
+            cum_lengths.push(acc);
+        }
+        let total_length = cum_lengths.last().copied().unwrap_or(0.0);
+
+        Self {
+            samples,
+            cum_lengths,
+            total_length: if total_length > 0.0 {
+                total_length
+            } else {
+                1.0
+            },
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+
+    /// Map arc-fraction s in [0..1] to a world point on the path by linear
+    /// interpolation in samples.
+    pub fn world_pos(&self, s: f32) -> (f32, f32, f32) {
+        if self.samples.is_empty() {
+            return (0.0, 0.0, 0.0);
+        }
+        let s_clamped = s.clamp(0.0, 1.0);
+        let target = s_clamped * self.total_length;
+        // binary search in cum_lengths
+        let idx = match self
+            .cum_lengths
+            .binary_search_by(|v| v.partial_cmp(&target).unwrap_or(std::cmp::Ordering::Equal))
+        {
+            Ok(i) => i,
+            Err(i) => i,
+        };
+        if idx == 0 {
+            self.samples[0]
+        } else if idx >= self.samples.len() {
+            *self.samples.last().unwrap()
+        } else {
+            // interpolate between idx-1 and idx
+            let i1 = idx - 1;
+            let i2 = idx;
+            let l1 = self.cum_lengths[i1];
+            let l2 = self.cum_lengths[i2];
+            let denom = (l2 - l1).max(1e-6);
+            let t = (target - l1) / denom;
+            let (x1, y1, z1) = self.samples[i1];
+            let (x2, y2, z2) = self.samples[i2];
+            let x = x1 * (1.0 - t) + x2 * t;
+            let y = y1 * (1.0 - t) + y2 * t;
+            let z = z1 * (1.0 - t) + z2 * t;
+            (x, y, z)
+        }
+    }
+
+    /// Center of a chain marble at arc-fraction s: the path point raised by `CHAIN_HEIGHT`.
+    pub fn chain_pos(&self, s: f32) -> (f32, f32, f32) {
+        let (x, y, z) = self.world_pos(s);
+        (x, y + CHAIN_HEIGHT, z)
+    }
+
+    /// Unit direction of travel (toward s = 1) at arc-fraction s.
+    pub fn tangent(&self, s: f32) -> (f32, f32, f32) {
+        let step = 0.5 / self.total_length.max(0.1);
+        let (ax, ay, az) = self.world_pos((s - step).max(0.0));
+        let (bx, by, bz) = self.world_pos((s + step).min(1.0));
+        let (dx, dy, dz) = (bx - ax, by - ay, bz - az);
+        let len = (dx * dx + dy * dy + dz * dz).sqrt();
+        if len > f32::EPSILON {
+            (dx / len, dy / len, dz / len)
+        } else {
+            (0.0, 0.0, 0.0)
+        }
+    }
+
+    /// Chain marble centers at each sample as `[x, y, z]`, for clients that place
+    /// chain marbles themselves.
+    pub fn points(&self) -> Vec<[f32; 3]> {
+        self.samples
+            .iter()
+            .map(|&(x, y, z)| [x, y + CHAIN_HEIGHT, z])
+            .collect()
+    }
+
+    /// Stable FNV-1a hash of the samples, so clients can cache paths across joins.
+    pub fn hash(&self) -> String {
+        let mut h: u64 = 0xcbf2_9ce4_8422_2325;
+        for &(x, y, z) in self.samples.iter() {
+            let bytes = [x.to_le_bytes(), y.to_le_bytes(), z.to_le_bytes()];
+            for b in bytes.into_iter().flatten() {
+                h ^= b as u64;
+                h = h.wrapping_mul(0x0100_0000_01b3);
+            }
+        }
+        format!("{:016x}", h)
+    }
+}
+
+impl Default for SampledPath {
+    fn default() -> Self {
+        Self::from_points(&[])
+    }
+}
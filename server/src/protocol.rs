@@ -0,0 +1,300 @@
+use crate::events::GameEvent;
+use crate::game::{GamePhase, LobbyPlayer, Player, RoundSummary};
+use crate::level::LevelInfo;
+use crate::path::SampledPath;
+use crate::room::RoomInfo;
+use crate::snapshot::{self, DeltaSnapshot, StateSnapshot};
+use axum::extract::ws::Message;
+use serde::{Deserialize, Serialize};
+
+/// Newest protocol version this server speaks.
+///
+/// - v1: chain marbles are sent as world positions inside `marbles`.
+/// - v2: each sampled level path is sent once in a `path` message after `welcome`,
+///   and chain marbles are sent as `(id, track, s, color, frozen, hidden)` entries in `chain`.
+pub const PROTOCOL_VERSION: u32 = 2;
+/// Oldest protocol version this server still accepts in a `hello`.
+pub const MIN_PROTOCOL_VERSION: u32 = 1;
+
+/// Messages sent by clients. Every frame is a JSON object tagged by `type`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ClientMessage {
+    /// Handshake; must be the first message on a connection.
+    Hello {
+        version: u32,
+    },
+    ListRooms,
+    ListLevels,
+    CreateRoom {
+        #[serde(default)]
+        name: Option<String>,
+        #[serde(rename = "maxPlayers", default)]
+        max_players: Option<usize>,
+        #[serde(default)]
+        level: Option<String>,
+        /// Level ids to play in order; overrides `level` when non-empty.
+        #[serde(default)]
+        campaign: Option<Vec<String>>,
+    },
+    JoinRoom {
+        #[serde(rename = "roomId")]
+        room_id: String,
+        #[serde(default)]
+        token: Option<String>,
+        /// Snapshot encoding this client wants; JSON unless it opts in to binary.
+        #[serde(default)]
+        encoding: Encoding,
+        /// Name shown to the other players in the room lobby.
+        #[serde(default)]
+        name: Option<String>,
+    },
+    /// Toggle this player's ready flag in the room lobby.
+    Ready {
+        ready: bool,
+    },
+    /// Host only: start the match once everyone is ready.
+    StartMatch,
+    Aim {
+        yaw: f32,
+    },
+    Shoot,
+    /// Exchange the loaded and next marble colors.
+    Swap,
+    /// Acknowledge receipt of snapshot `seq`; later deltas are encoded against it.
+    Ack {
+        seq: u64,
+    },
+    /// Ask for a full snapshot, e.g. after losing track of delta bases.
+    RequestKeyframe,
+    /// Start the room's level over once the game is finished.
+    Restart,
+    Ping {
+        #[serde(default)]
+        timestamp: Option<i64>,
+    },
+}
+
+impl ClientMessage {
+    /// Every `type` tag understood by `ClientMessage`.
+    const TYPES: &'static [&'static str] = &[
+        "hello",
+        "list_rooms",
+        "list_levels",
+        "create_room",
+        "join_room",
+        "ready",
+        "start_match",
+        "aim",
+        "shoot",
+        "swap",
+        "ack",
+        "request_keyframe",
+        "restart",
+        "ping",
+    ];
+
+    /// Parse a text frame, distinguishing unknown message types from known
+    /// types with missing or invalid fields.
+    pub fn decode(text: &str) -> Result<Self, ProtocolError> {
+        let value: serde_json::Value = serde_json::from_str(text)
+            .map_err(|e| ProtocolError::new(ErrorCode::MalformedMessage, e.to_string()))?;
+
+        let msg_type = match value.get("type").and_then(|t| t.as_str()) {
+            Some(t) => t.to_string(),
+            None => {
+                return Err(ProtocolError::new(
+                    ErrorCode::MalformedMessage,
+                    "Message must be an object with a string `type` field",
+                ))
+            }
+        };
+
+        if !Self::TYPES.contains(&msg_type.as_str()) {
+            return Err(ProtocolError::new(
+                ErrorCode::UnsupportedMessage,
+                format!("Unsupported message type: {}", msg_type),
+            ));
+        }
+
+        serde_json::from_value(value).map_err(|e| {
+            ProtocolError::new(
+                ErrorCode::MalformedMessage,
+                format!("Invalid {} message: {}", msg_type, e),
+            )
+        })
+    }
+}
+
+/// Messages sent by the server.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ServerMessage {
+    /// Handshake reply carrying the negotiated protocol version.
+    Hello {
+        version: u32,
+        min_version: u32,
+        max_version: u32,
+    },
+    RoomsList {
+        rooms: Vec<RoomInfo>,
+    },
+    LevelsList {
+        levels: Vec<LevelInfo>,
+    },
+    RoomCreated {
+        #[serde(rename = "roomId")]
+        room_id: String,
+        name: String,
+        #[serde(rename = "maxPlayers")]
+        max_players: usize,
+        level: String,
+        path: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        campaign: Option<Vec<String>>,
+    },
+    /// Lobby view of a room, sent to its clients whenever it changes.
+    RoomState {
+        #[serde(rename = "roomId")]
+        room_id: String,
+        host: Option<u64>,
+        phase: GamePhase,
+        players: Vec<LobbyPlayer>,
+    },
+    Welcome {
+        token: String,
+        player: Player,
+        #[serde(rename = "roomId")]
+        room_id: String,
+        encoding: Encoding,
+    },
+    /// Sampled path of one level track, sent to v2+ clients after `welcome`
+    /// (once per track). `points` are chain marble centers, following the
+    /// curve's elevation. Chain entries on `track` have `s` as the fraction of
+    /// `total_length` (3D arc length) along these points.
+    Path {
+        track: usize,
+        hash: String,
+        points: Vec<[f32; 3]>,
+        total_length: f32,
+    },
+    State(StateSnapshot),
+    Delta(DeltaSnapshot),
+    /// Gameplay events from simulation step `tick` (power-ups, ...).
+    Events {
+        tick: u64,
+        events: Vec<GameEvent>,
+    },
+    /// Sent once when a round finishes (clear-mode victory or defeat).
+    RoundSummary(RoundSummary),
+    Pong {
+        timestamp: i64,
+        /// Echo of the ping's `timestamp` so clients can measure round trips.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        client_timestamp: Option<i64>,
+    },
+    Error {
+        code: ErrorCode,
+        message: String,
+    },
+}
+
+impl ServerMessage {
+    pub fn error(code: ErrorCode, message: impl Into<String>) -> Self {
+        ServerMessage::Error {
+            code,
+            message: message.into(),
+        }
+    }
+
+    /// `path` message for track `track` of a level.
+    pub fn path(track: usize, path: &SampledPath) -> Self {
+        ServerMessage::Path {
+            track,
+            hash: path.hash(),
+            points: path.points(),
+            total_length: path.total_length,
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        // All server messages are plain data; serialization cannot fail.
+        serde_json::to_string(self).expect("server message serializes to JSON")
+    }
+
+    pub fn to_ws(&self) -> Message {
+        Message::Text(self.to_json())
+    }
+
+    /// Encode for a client that negotiated `encoding`. Only state snapshots
+    /// and deltas have a binary form; everything else stays JSON text.
+    pub fn encode(&self, encoding: Encoding) -> Message {
+        match (self, encoding) {
+            (ServerMessage::State(state), Encoding::Binary) => {
+                Message::Binary(snapshot::encode_binary(state))
+            }
+            (ServerMessage::Delta(delta), Encoding::Binary) => {
+                Message::Binary(snapshot::encode_binary_delta(delta))
+            }
+            _ => self.to_ws(),
+        }
+    }
+}
+
+/// Wire encoding for state snapshots, chosen per client in `join_room`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Encoding {
+    #[default]
+    Json,
+    Binary,
+}
+
+/// Machine-readable error codes carried in `error` replies.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    MalformedMessage,
+    UnsupportedMessage,
+    UnsupportedVersion,
+    HandshakeRequired,
+    RoomNotFound,
+    RoomFull,
+    RoomLimitReached,
+    UnknownLevel,
+    LevelLoadFailed,
+    EndlessCampaignStage,
+    NotInRoom,
+    GameNotFinished,
+    NotHost,
+    MatchAlreadyStarted,
+    PlayersNotReady,
+}
+
+#[derive(Debug, Clone)]
+pub struct ProtocolError {
+    pub code: ErrorCode,
+    pub message: String,
+}
+
+impl ProtocolError {
+    pub fn new(code: ErrorCode, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
+
+    pub fn to_message(&self) -> ServerMessage {
+        ServerMessage::error(self.code, self.message.clone())
+    }
+}
+
+/// Pick the version both sides speak, or `None` if the client is too old.
+pub fn negotiate_version(client_version: u32) -> Option<u32> {
+    if client_version < MIN_PROTOCOL_VERSION {
+        None
+    } else {
+        Some(client_version.min(PROTOCOL_VERSION))
+    }
+}
@@ -1,11 +1,21 @@
-use crate::game::{GameState, SharedGame};
+use crate::config::Config;
+use crate::game::{CampaignStage, GamePhase, GameState, SharedGame};
+use crate::level::{LevelError, LevelFile, LevelInfo, LevelRegistry};
 use crate::network::Clients;
+use crate::protocol::{Encoding, ErrorCode, ServerMessage};
+use crate::snapshot::{ChainFormat, SnapshotHistory};
+use axum::extract::ws::Message;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::fmt;
 use std::net::SocketAddr;
+use std::path::Path;
 use std::sync::Arc;
-use tokio::sync::RwLock;
-use tracing::info;
+use std::time::Duration;
+use tokio::sync::{oneshot, RwLock};
+use tokio::task::JoinHandle;
+use tokio::time::{Instant, MissedTickBehavior};
+use tracing::{info, warn};
 
 pub type SharedRoomManager = Arc<RwLock<RoomManager>>;
 
@@ -17,6 +27,10 @@ pub struct RoomInfo {
     pub max_players: usize,
     pub created_at: i64,
     pub level: Option<String>,
+    /// Level ids of a campaign room, in play order.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub campaign: Option<Vec<String>>,
+    pub tick_rate_ms: u64,
 }
 
 pub struct Room {
@@ -25,8 +39,15 @@ pub struct Room {
     pub max_players: usize,
     pub created_at: i64,
     pub level: Option<String>,
+    /// Level ids played in order, when the room is a campaign.
+    pub campaign: Option<Vec<String>>,
+    pub tick_rate: Duration,
+    /// Client that created the room; becomes host when it joins.
+    pub creator: Option<SocketAddr>,
     pub game: SharedGame,
     pub clients: Clients,
+    // simulation task; stopped via `shutdown` (or when the room is dropped)
+    sim: std::sync::Mutex<Option<(oneshot::Sender<()>, JoinHandle<()>)>>,
 }
 
 impl Room {
@@ -34,32 +55,69 @@ impl Room {
         id: String,
         name: String,
         max_players: usize,
-        level: Option<String>,
-        path_json: Option<String>,
-    ) -> Self {
-        let game = Arc::new(RwLock::new(match path_json.as_deref() {
-            Some(p) => GameState::from_path_json(p),
-            None => GameState::default(),
-        }));
+        stages: &[(String, String)],
+        tick_rate: Duration,
+        creator: Option<SocketAddr>,
+    ) -> Result<Self, LevelError> {
+        // one (level id, path json) pair is a plain room; more make a campaign
+        let (game, campaign) = match stages {
+            [(_, path_json)] => (GameState::from_path_json(path_json)?, None),
+            _ => {
+                let mut loaded = Vec::with_capacity(stages.len());
+                for (level, path_json) in stages {
+                    loaded.push(CampaignStage {
+                        id: level.clone(),
+                        level: LevelFile::load(Path::new(path_json))?,
+                    });
+                }
+                let ids = stages.iter().map(|(level, _)| level.clone()).collect();
+                (GameState::from_campaign(loaded), Some(ids))
+            }
+        };
+        let level = stages.first().map(|(level, _)| level.clone());
+        let game = Arc::new(RwLock::new(game));
         let clients = Arc::new(RwLock::new(HashMap::new()));
         let created_at = chrono::Utc::now().timestamp();
 
         info!(
-            "Created room: {} ({}) level={} path={}",
+            "Created room: {} ({}) level={} stages={} tick={:?}",
             name,
             id,
             level.as_deref().unwrap_or("default"),
-            path_json.as_deref().unwrap_or("default")
+            stages.len(),
+            tick_rate
         );
 
-        Self {
+        let (sim_stop, stop_rx) = oneshot::channel();
+        let sim_task = tokio::spawn(run_simulation(
+            id.clone(),
+            game.clone(),
+            clients.clone(),
+            tick_rate,
+            stop_rx,
+        ));
+
+        Ok(Self {
             id,
             name,
             max_players,
             created_at,
             level,
+            campaign,
+            tick_rate,
+            creator,
             game,
             clients,
+            sim: std::sync::Mutex::new(Some((sim_stop, sim_task))),
+        })
+    }
+
+    /// Stop the simulation task and wait for its current tick to finish.
+    pub async fn shutdown(&self) {
+        let sim = self.sim.lock().ok().and_then(|mut sim| sim.take());
+        if let Some((stop, task)) = sim {
+            let _ = stop.send(());
+            let _ = task.await;
         }
     }
 
@@ -72,13 +130,65 @@ impl Room {
     }
 
     pub async fn info(&self) -> RoomInfo {
+        // campaign rooms report the stage being played
+        let level = match self.game.read().await.campaign_progress() {
+            Some(progress) => Some(progress.level),
+            None => self.level.clone(),
+        };
         RoomInfo {
             id: self.id.clone(),
             name: self.name.clone(),
             players: self.player_count().await,
             max_players: self.max_players,
             created_at: self.created_at,
-            level: self.level.clone(),
+            level,
+            campaign: self.campaign.clone(),
+            tick_rate_ms: self.tick_rate.as_millis() as u64,
+        }
+    }
+}
+
+/// Why `create_room` refused to create a room.
+#[derive(Debug)]
+pub enum CreateRoomError {
+    TooManyRooms {
+        max_rooms: usize,
+    },
+    UnknownLevel {
+        level: String,
+    },
+    /// A campaign stage before the last has no marble budget, so it can't be won.
+    EndlessStage {
+        level: String,
+    },
+    LevelLoad(LevelError),
+}
+
+impl CreateRoomError {
+    pub fn to_message(&self) -> ServerMessage {
+        let code = match self {
+            CreateRoomError::TooManyRooms { .. } => ErrorCode::RoomLimitReached,
+            CreateRoomError::UnknownLevel { .. } => ErrorCode::UnknownLevel,
+            CreateRoomError::EndlessStage { .. } => ErrorCode::EndlessCampaignStage,
+            CreateRoomError::LevelLoad(_) => ErrorCode::LevelLoadFailed,
+        };
+        ServerMessage::error(code, self.to_string())
+    }
+}
+
+impl fmt::Display for CreateRoomError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CreateRoomError::TooManyRooms { max_rooms } => {
+                write!(f, "Server is at its limit of {} rooms", max_rooms)
+            }
+            CreateRoomError::UnknownLevel { level } => write!(f, "Unknown level: {}", level),
+            CreateRoomError::EndlessStage { level } => write!(
+                f,
+                "Level {} has no marble budget, so it can only be the last campaign stage",
+                level
+            ),
+            CreateRoomError::LevelLoad(e) => write!(f, "Failed to load level: {}", e),
         }
     }
 }
@@ -87,41 +197,107 @@ pub struct RoomManager {
     rooms: HashMap<String, Arc<RwLock<Room>>>,
     player_rooms: HashMap<SocketAddr, String>, // tracks which room each player is in
     next_room_id: u64,
+    // limits and settings from the server config
+    max_rooms: usize,
+    max_players_per_room: usize,
+    tick_rate: Duration,
+    paused_room_grace: Duration,
+    levels: Arc<LevelRegistry>,
+    // when each room was first found empty by `cleanup_empty_rooms`
+    empty_since: HashMap<String, Instant>,
 }
 
 impl RoomManager {
-    pub fn new() -> Self {
+    pub fn new(config: &Config, levels: Arc<LevelRegistry>) -> Self {
         Self {
             rooms: HashMap::new(),
             player_rooms: HashMap::new(),
             next_room_id: 1,
+            max_rooms: config.max_rooms,
+            max_players_per_room: config.max_players_per_room,
+            tick_rate: config.tick_rate(),
+            paused_room_grace: config.paused_room_grace(),
+            levels,
+            empty_since: HashMap::new(),
         }
     }
 
-    pub fn create_room(
-        &mut self,
-        name: String,
-        max_players: usize,
-        level: Option<String>,
-    ) -> String {
-        self.create_room_with_level(name, max_players, level, None)
+    /// Levels rooms can be created with.
+    pub fn levels(&self) -> Vec<LevelInfo> {
+        self.levels.list()
+    }
+
+    /// Path json for a registered level, e.g. `first-level` -> `<paths_dir>/first-level.json`.
+    pub fn level_path(&self, level: &str) -> Option<String> {
+        self.levels
+            .get(level)
+            .map(|info| info.path.to_string_lossy().into_owned())
+    }
+
+    /// Clamp a requested player cap to the configured per-room limit.
+    pub fn clamp_max_players(&self, max_players: usize) -> usize {
+        max_players.clamp(1, self.max_players_per_room)
     }
 
-    pub fn create_room_with_level(
+    /// Create a room playing `levels` in order; a single level is a plain room,
+    /// several make a campaign.
+    pub fn create_room(
         &mut self,
         name: String,
         max_players: usize,
-        level: Option<String>,
-        path_json: Option<String>,
-    ) -> String {
+        levels: &[String],
+        creator: Option<SocketAddr>,
+    ) -> Result<String, CreateRoomError> {
+        if self.rooms.len() >= self.max_rooms {
+            warn!(
+                "Refusing to create room {}: {} rooms exist",
+                name,
+                self.rooms.len()
+            );
+            return Err(CreateRoomError::TooManyRooms {
+                max_rooms: self.max_rooms,
+            });
+        }
+        let mut stages = Vec::with_capacity(levels.len());
+        for (i, level) in levels.iter().enumerate() {
+            let info = self
+                .levels
+                .get(level)
+                .ok_or_else(|| CreateRoomError::UnknownLevel {
+                    level: level.clone(),
+                })?;
+            // a campaign only moves on when a stage is won
+            if info.endless && i + 1 < levels.len() {
+                return Err(CreateRoomError::EndlessStage {
+                    level: level.clone(),
+                });
+            }
+            stages.push((level.clone(), info.path.to_string_lossy().into_owned()));
+        }
+        if stages.is_empty() {
+            return Err(CreateRoomError::UnknownLevel {
+                level: String::new(),
+            });
+        }
+
         let id = format!("room-{}", self.next_room_id);
+        let room = Room::new(
+            id.clone(),
+            name,
+            self.clamp_max_players(max_players),
+            &stages,
+            self.tick_rate,
+            creator,
+        )
+        .map_err(|e| {
+            warn!("Failed to load levels {}: {}", levels.join(", "), e);
+            CreateRoomError::LevelLoad(e)
+        })?;
         self.next_room_id += 1;
-
-        let room = Room::new(id.clone(), name, max_players, level, path_json);
         self.rooms.insert(id.clone(), Arc::new(RwLock::new(room)));
 
         info!("Room created: {}", id);
-        id
+        Ok(id)
     }
 
     pub fn get_room(&self, room_id: &str) -> Option<Arc<RwLock<Room>>> {
@@ -134,7 +310,7 @@ impl RoomManager {
             let room = room_lock.read().await;
             rooms.push(room.info().await);
         }
-        rooms.sort_by(|a, b| a.created_at.cmp(&b.created_at));
+        rooms.sort_by_key(|r| r.created_at);
         rooms
     }
 
@@ -142,37 +318,176 @@ impl RoomManager {
         self.player_rooms.insert(addr, room_id);
     }
 
-    pub fn get_player_room(&self, addr: &SocketAddr) -> Option<String> {
-        self.player_rooms.get(addr).cloned()
-    }
-
     pub fn remove_player(&mut self, addr: &SocketAddr) {
         self.player_rooms.remove(addr);
     }
 
+    /// Remove rooms without players. A room whose match is paused survives until
+    /// `paused_room_grace` has passed since a sweep first found it empty.
     pub async fn cleanup_empty_rooms(&mut self) {
         let mut to_remove = Vec::new();
+        let now = Instant::now();
 
         for (id, room_lock) in &self.rooms {
             let room = room_lock.read().await;
-            if room.player_count().await == 0 {
+            if room.player_count().await > 0 {
+                self.empty_since.remove(id);
+                continue;
+            }
+            // a paused match is kept for `paused_room_grace` so its players can rejoin
+            let since = *self.empty_since.entry(id.clone()).or_insert(now);
+            let paused = room.game.read().await.phase == GamePhase::Paused;
+            if !paused || now.duration_since(since) >= self.paused_room_grace {
                 to_remove.push(id.clone());
             }
         }
 
         for id in to_remove {
-            self.rooms.remove(&id);
+            self.empty_since.remove(&id);
+            if let Some(room_lock) = self.rooms.remove(&id) {
+                room_lock.read().await.shutdown().await;
+            }
             info!("Removed empty room: {}", id);
         }
     }
+}
 
-    pub fn room_count(&self) -> usize {
-        self.rooms.len()
+/// Send every client in the room its lobby view (`room_state`).
+pub async fn broadcast_room_state(room_id: &str, game: &SharedGame, clients: &Clients) {
+    let msg = {
+        let gs = game.read().await;
+        ServerMessage::RoomState {
+            room_id: room_id.to_string(),
+            host: gs.host,
+            phase: gs.phase,
+            players: gs.lobby_players(),
+        }
+    }
+    .to_ws();
+    for client in clients.read().await.values() {
+        let _ = client.tx.send(msg.clone());
     }
 }
 
-impl Default for RoomManager {
-    fn default() -> Self {
-        Self::new()
+/// Most fixed steps run in one wakeup to catch up after a stall; any further
+/// backlog is dropped so a stalled room slows down instead of fast-forwarding.
+const MAX_CATCH_UP_STEPS: u32 = 5;
+
+/// Per-room simulation loop: advance the game in fixed steps of `tick_rate` driven by
+/// measured wall-clock time, then broadcast a snapshot, until `stop` fires or its
+/// sender is dropped along with the room.
+async fn run_simulation(
+    room_id: String,
+    game: SharedGame,
+    clients: Clients,
+    tick_rate: Duration,
+    mut stop: oneshot::Receiver<()>,
+) {
+    let mut interval = tokio::time::interval(tick_rate);
+    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    let mut history = SnapshotHistory::new();
+    let dt = tick_rate.as_secs_f32();
+    let mut last = Instant::now();
+    let mut accumulator = Duration::ZERO;
+    let mut overruns: u64 = 0;
+    // paths last sent to path-param clients; campaign rooms swap them between stages
+    let mut sent_paths = game.read().await.paths();
+
+    loop {
+        tokio::select! {
+            _ = &mut stop => break,
+            _ = interval.tick() => {}
+        }
+
+        let started = Instant::now();
+        accumulator += started - last;
+        last = started;
+
+        // advance game state in fixed steps and build snapshot
+        let (snapshot, paths, events, summary) = {
+            let mut gs = game.write().await;
+            let mut steps = 0;
+            while accumulator >= tick_rate && steps < MAX_CATCH_UP_STEPS {
+                gs.update(dt);
+                accumulator -= tick_rate;
+                steps += 1;
+            }
+            if accumulator >= tick_rate {
+                warn!(
+                    "Room {} fell {:?} behind at tick {}; dropping backlog",
+                    room_id, accumulator, gs.tick
+                );
+                accumulator = Duration::ZERO;
+            }
+            (
+                gs.snapshot(),
+                gs.paths(),
+                gs.take_events(),
+                gs.take_summary(),
+            )
+        };
+        let snapshot = history.record(snapshot);
+
+        // broadcast to all clients in this room; each client gets a delta against
+        // its last acked snapshot (or a keyframe), encoded at most once per base/format
+        let mut encoded: HashMap<(Option<u64>, Encoding, ChainFormat), Message> = HashMap::new();
+        let events = (!events.is_empty()).then(|| {
+            ServerMessage::Events {
+                tick: snapshot.tick,
+                events,
+            }
+            .to_ws()
+        });
+        let summary = summary.map(|summary| ServerMessage::RoundSummary(summary).to_ws());
+        let paths_changed = paths.len() != sent_paths.len()
+            || paths
+                .iter()
+                .zip(&sent_paths)
+                .any(|(a, b)| !Arc::ptr_eq(a, b));
+        let new_paths: Vec<Message> = if paths_changed {
+            sent_paths = paths.clone();
+            paths
+                .iter()
+                .enumerate()
+                .map(|(track, path)| ServerMessage::path(track, path).to_ws())
+                .collect()
+        } else {
+            Vec::new()
+        };
+        let mut clients_map = clients.write().await;
+        for (_addr, client) in clients_map.iter_mut() {
+            if client.chain_format == ChainFormat::PathParams {
+                for msg in new_paths.iter() {
+                    let _ = client.tx.send(msg.clone());
+                }
+            }
+            let base = client.snapshots.select_base(&history);
+            let msg = encoded
+                .entry((base, client.encoding, client.chain_format))
+                .or_insert_with(|| {
+                    history
+                        .message_for(&snapshot, base, client.chain_format, &paths)
+                        .encode(client.encoding)
+                });
+            let _ = client.tx.send(msg.clone());
+            if let Some(events) = &events {
+                let _ = client.tx.send(events.clone());
+            }
+            if let Some(summary) = &summary {
+                let _ = client.tx.send(summary.clone());
+            }
+        }
+        drop(clients_map);
+
+        let elapsed = started.elapsed();
+        if elapsed > tick_rate {
+            overruns += 1;
+            warn!(
+                "Room {} tick {} took {:?}, over its {:?} budget ({} overruns so far)",
+                room_id, snapshot.tick, elapsed, tick_rate, overruns
+            );
+        }
     }
+
+    info!("Simulation for room {} stopped", room_id);
 }
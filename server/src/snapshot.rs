@@ -0,0 +1,675 @@
+use crate::game::{GamePhase, Marble, MarbleColor, Player, PowerUp, RoundOutcome};
+use crate::path::SampledPath;
+use crate::protocol::ServerMessage;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::Arc;
+
+/// Authoritative state broadcast to clients each tick.
+#[derive(Debug, Clone, Serialize)]
+pub struct StateSnapshot {
+    /// Sequence number assigned by `SnapshotHistory::record`; clients ack it.
+    pub seq: u64,
+    /// Simulation step this snapshot was taken after.
+    pub tick: u64,
+    pub players: Vec<Player>,
+    /// Free (shot) marbles. Protocol v1 clients also get chain marbles here, as world
+    /// positions, minus those hidden in tunnels.
+    pub marbles: Vec<Marble>,
+    /// Chain marbles as positions along the level path (protocol v2+).
+    pub chain: Vec<ChainEntry>,
+    pub score: u32,
+    pub game_over: bool,
+    /// How the round ended, once `phase` is `finished`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub outcome: Option<RoundOutcome>,
+    /// Campaign rooms: which stage is being played.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub campaign: Option<CampaignProgress>,
+    pub phase: GamePhase,
+    /// Seconds left before the match starts while `phase` is `countdown`.
+    pub countdown: f32,
+    /// Most marbles that reached the end of any one track.
+    pub marbles_reached_end: u32,
+    pub difficulty: Difficulty,
+}
+
+impl StateSnapshot {
+    /// Protocol v1 view: visible chain marbles resolved to world positions on their
+    /// track's path and merged into `marbles`.
+    fn with_world_positions(&self, paths: &[Arc<SampledPath>]) -> StateSnapshot {
+        let mut marbles = self.marbles.clone();
+        marbles.extend(self.chain.iter().filter(|c| !c.hidden).filter_map(|c| {
+            let (x, y, z) = paths.get(c.track)?.chain_pos(c.s);
+            Some(Marble {
+                id: c.id,
+                x,
+                y,
+                z,
+                vx: 0.0,
+                vy: 0.0,
+                vz: 0.0,
+                life: 9999.0,
+                color: c.color,
+                owner: None,
+            })
+        }));
+        StateSnapshot {
+            seq: self.seq,
+            tick: self.tick,
+            players: self.players.clone(),
+            marbles,
+            chain: Vec::new(),
+            score: self.score,
+            game_over: self.game_over,
+            outcome: self.outcome,
+            campaign: self.campaign.clone(),
+            phase: self.phase,
+            countdown: self.countdown,
+            marbles_reached_end: self.marbles_reached_end,
+            difficulty: self.difficulty.clone(),
+        }
+    }
+}
+
+/// A chain marble by its arc-length fraction `s` along the path of `track`,
+/// as sent in that track's `path` message.
+#[derive(Debug, Clone, Serialize)]
+pub struct ChainEntry {
+    pub id: u64,
+    pub track: usize,
+    pub s: f32,
+    pub color: MarbleColor,
+    pub frozen: bool,
+    /// Inside a tunnel of the level: out of sight and can't be hit.
+    pub hidden: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub power: Option<PowerUp>,
+}
+
+/// How chain marbles are represented for a client.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ChainFormat {
+    /// Legacy: world positions mixed into `marbles`, resolved server-side.
+    WorldPositions,
+    /// `(id, s, color, frozen)` entries in `chain`, placed by the client along the path.
+    PathParams,
+}
+
+impl ChainFormat {
+    pub fn for_version(version: u32) -> Self {
+        if version >= 2 {
+            ChainFormat::PathParams
+        } else {
+            ChainFormat::WorldPositions
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Difficulty {
+    pub elapsed_time: f32,
+    pub chain_speed: f32,
+    pub base_chain_speed: f32,
+    pub max_chain_speed: f32,
+    pub speed_ramp_per_sec: f32,
+}
+
+/// Position of a campaign room in its level list.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CampaignProgress {
+    /// Index of the current stage, from 0.
+    pub stage: usize,
+    pub stages: usize,
+    /// Level id of the current stage.
+    pub level: String,
+}
+
+/// Changes between the acknowledged snapshot `base_seq` and snapshot `seq`.
+///
+/// Clients rebuild the full state by applying this to their copy of `base_seq`:
+/// drop removed ids, insert added marbles, replace changed players and patch
+/// only the fields present in each `MarbleDelta`. Scalars are always sent.
+#[derive(Debug, Clone, Serialize)]
+pub struct DeltaSnapshot {
+    pub seq: u64,
+    pub base_seq: u64,
+    pub tick: u64,
+    pub players_changed: Vec<Player>,
+    pub players_removed: Vec<u64>,
+    pub marbles_added: Vec<Marble>,
+    pub marbles_changed: Vec<MarbleDelta>,
+    pub marbles_removed: Vec<u64>,
+    pub chain_added: Vec<ChainEntry>,
+    pub chain_changed: Vec<ChainDelta>,
+    pub chain_removed: Vec<u64>,
+    pub score: u32,
+    pub game_over: bool,
+    /// How the round ended, once `phase` is `finished`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub outcome: Option<RoundOutcome>,
+    /// Campaign rooms: which stage is being played.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub campaign: Option<CampaignProgress>,
+    pub phase: GamePhase,
+    /// Seconds left before the match starts while `phase` is `countdown`.
+    pub countdown: f32,
+    /// Most marbles that reached the end of any one track.
+    pub marbles_reached_end: u32,
+    pub difficulty: Difficulty,
+}
+
+/// Fields of an existing marble that differ from the base snapshot.
+/// A marble's id, color and owner never change, so they are not diffed.
+#[derive(Debug, Clone, Serialize)]
+pub struct MarbleDelta {
+    pub id: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub x: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub y: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub z: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub vx: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub vy: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub vz: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub life: Option<f32>,
+}
+
+impl MarbleDelta {
+    fn between(base: &Marble, cur: &Marble) -> Option<Self> {
+        fn changed(a: f32, b: f32) -> Option<f32> {
+            (a != b).then_some(b)
+        }
+        let d = MarbleDelta {
+            id: cur.id,
+            x: changed(base.x, cur.x),
+            y: changed(base.y, cur.y),
+            z: changed(base.z, cur.z),
+            vx: changed(base.vx, cur.vx),
+            vy: changed(base.vy, cur.vy),
+            vz: changed(base.vz, cur.vz),
+            life: changed(base.life, cur.life),
+        };
+        let any = [d.x, d.y, d.z, d.vx, d.vy, d.vz, d.life]
+            .iter()
+            .any(Option::is_some);
+        any.then_some(d)
+    }
+}
+
+/// Fields of a chain marble that differ from the base snapshot. A marble
+/// never leaves its track, so `track` is not diffed.
+#[derive(Debug, Clone, Serialize)]
+pub struct ChainDelta {
+    pub id: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub s: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub frozen: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hidden: Option<bool>,
+}
+
+impl ChainDelta {
+    fn between(base: &ChainEntry, cur: &ChainEntry) -> Option<Self> {
+        let d = ChainDelta {
+            id: cur.id,
+            s: (base.s != cur.s).then_some(cur.s),
+            frozen: (base.frozen != cur.frozen).then_some(cur.frozen),
+            hidden: (base.hidden != cur.hidden).then_some(cur.hidden),
+        };
+        (d.s.is_some() || d.frozen.is_some() || d.hidden.is_some()).then_some(d)
+    }
+}
+
+/// Snapshots kept for delta encoding: 64 ticks is ~3 s at 20 Hz. Clients whose
+/// last ack is older than that get a keyframe instead.
+const HISTORY_LEN: usize = 64;
+
+/// Recent snapshots of one room, keyed by sequence number.
+#[derive(Debug)]
+pub struct SnapshotHistory {
+    next_seq: u64,
+    recent: VecDeque<Arc<StateSnapshot>>,
+}
+
+impl SnapshotHistory {
+    pub fn new() -> Self {
+        Self {
+            next_seq: 1,
+            recent: VecDeque::with_capacity(HISTORY_LEN),
+        }
+    }
+
+    /// Stamp `snapshot` with the next sequence number and remember it.
+    pub fn record(&mut self, mut snapshot: StateSnapshot) -> Arc<StateSnapshot> {
+        snapshot.seq = self.next_seq;
+        self.next_seq += 1;
+        let snapshot = Arc::new(snapshot);
+        if self.recent.len() == HISTORY_LEN {
+            self.recent.pop_front();
+        }
+        self.recent.push_back(snapshot.clone());
+        snapshot
+    }
+
+    pub fn get(&self, seq: u64) -> Option<&Arc<StateSnapshot>> {
+        let first = self.recent.front()?.seq;
+        let idx = seq.checked_sub(first)? as usize;
+        self.recent.get(idx)
+    }
+
+    /// The message bringing a client from `base` (or nothing) up to `current`,
+    /// with the chain in the client's `format`.
+    pub fn message_for(
+        &self,
+        current: &StateSnapshot,
+        base: Option<u64>,
+        format: ChainFormat,
+        paths: &[Arc<SampledPath>],
+    ) -> ServerMessage {
+        let view = |snap: &StateSnapshot| -> StateSnapshot {
+            match format {
+                ChainFormat::PathParams => snap.clone(),
+                ChainFormat::WorldPositions => snap.with_world_positions(paths),
+            }
+        };
+        match base.and_then(|seq| self.get(seq)) {
+            Some(base) => match format {
+                ChainFormat::PathParams => ServerMessage::Delta(diff(base, current)),
+                ChainFormat::WorldPositions => {
+                    ServerMessage::Delta(diff(&view(base), &view(current)))
+                }
+            },
+            None => ServerMessage::State(view(current)),
+        }
+    }
+}
+
+impl Default for SnapshotHistory {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Per-client delta state: which snapshot the client has acknowledged and
+/// whether it asked for a fresh keyframe. Clients that never ack always get
+/// full snapshots.
+#[derive(Debug, Default)]
+pub struct SnapshotBuilder {
+    acked_seq: Option<u64>,
+    keyframe_requested: bool,
+}
+
+impl SnapshotBuilder {
+    pub fn ack(&mut self, seq: u64) {
+        self.acked_seq = Some(self.acked_seq.map_or(seq, |a| a.max(seq)));
+    }
+
+    /// Force a keyframe next tick; deltas resume once the client acks it.
+    pub fn request_keyframe(&mut self) {
+        self.keyframe_requested = true;
+        self.acked_seq = None;
+    }
+
+    /// Pick the snapshot the next update is encoded against; `None` means a keyframe.
+    pub fn select_base(&mut self, history: &SnapshotHistory) -> Option<u64> {
+        if std::mem::take(&mut self.keyframe_requested) {
+            return None;
+        }
+        self.acked_seq.filter(|&seq| history.get(seq).is_some())
+    }
+}
+
+fn diff(base: &StateSnapshot, cur: &StateSnapshot) -> DeltaSnapshot {
+    let base_players: HashMap<u64, &Player> = base.players.iter().map(|p| (p.id, p)).collect();
+    let cur_player_ids: HashSet<u64> = cur.players.iter().map(|p| p.id).collect();
+    let players_changed = cur
+        .players
+        .iter()
+        .filter(|p| match base_players.get(&p.id) {
+            Some(b) => !player_eq(b, p),
+            None => true,
+        })
+        .cloned()
+        .collect();
+    let players_removed = base
+        .players
+        .iter()
+        .map(|p| p.id)
+        .filter(|id| !cur_player_ids.contains(id))
+        .collect();
+
+    let base_marbles: HashMap<u64, &Marble> = base.marbles.iter().map(|m| (m.id, m)).collect();
+    let mut seen: HashSet<u64> = HashSet::with_capacity(cur.marbles.len());
+    let mut marbles_added = Vec::new();
+    let mut marbles_changed = Vec::new();
+    for m in cur.marbles.iter() {
+        seen.insert(m.id);
+        match base_marbles.get(&m.id) {
+            Some(b) => marbles_changed.extend(MarbleDelta::between(b, m)),
+            None => marbles_added.push(m.clone()),
+        }
+    }
+    let marbles_removed = base
+        .marbles
+        .iter()
+        .map(|m| m.id)
+        .filter(|id| !seen.contains(id))
+        .collect();
+
+    let base_chain: HashMap<u64, &ChainEntry> = base.chain.iter().map(|c| (c.id, c)).collect();
+    let mut seen: HashSet<u64> = HashSet::with_capacity(cur.chain.len());
+    let mut chain_added = Vec::new();
+    let mut chain_changed = Vec::new();
+    for c in cur.chain.iter() {
+        seen.insert(c.id);
+        match base_chain.get(&c.id) {
+            Some(b) => chain_changed.extend(ChainDelta::between(b, c)),
+            None => chain_added.push(c.clone()),
+        }
+    }
+    let chain_removed = base
+        .chain
+        .iter()
+        .map(|c| c.id)
+        .filter(|id| !seen.contains(id))
+        .collect();
+
+    DeltaSnapshot {
+        seq: cur.seq,
+        base_seq: base.seq,
+        tick: cur.tick,
+        players_changed,
+        players_removed,
+        marbles_added,
+        marbles_changed,
+        marbles_removed,
+        chain_added,
+        chain_changed,
+        chain_removed,
+        score: cur.score,
+        game_over: cur.game_over,
+        outcome: cur.outcome,
+        campaign: cur.campaign.clone(),
+        phase: cur.phase,
+        countdown: cur.countdown,
+        marbles_reached_end: cur.marbles_reached_end,
+        difficulty: cur.difficulty.clone(),
+    }
+}
+
+fn player_eq(a: &Player, b: &Player) -> bool {
+    a.x == b.x
+        && a.y == b.y
+        && a.z == b.z
+        && a.yaw == b.yaw
+        && a.loaded_color == b.loaded_color
+        && a.next_color == b.next_color
+}
+
+/// First byte of every binary frame, identifying the payload.
+pub const BINARY_STATE: u8 = 1;
+pub const BINARY_DELTA: u8 = 2;
+
+/// Second byte of every binary frame. Bumped whenever a record layout below
+/// changes, so clients can reject frames they would misparse.
+pub const BINARY_LAYOUT: u8 = 1;
+
+/// World units are quantized to 1/100 (centimetres) as i16, giving a ±327 range.
+const POSITION_SCALE: f32 = 100.0;
+/// Yaw in radians is quantized to 1/10000 as i16 (±3.27 rad covers ±π).
+const YAW_SCALE: f32 = 10000.0;
+/// Path fraction `s` in [0..1] is quantized to u16.
+const PATH_SCALE: f32 = u16::MAX as f32;
+
+/// Encode a snapshot into the compact binary layout sent as `Message::Binary`.
+///
+/// All integers are little-endian:
+///
+/// ```text
+/// u8  kind (BINARY_STATE)
+/// u8  layout (BINARY_LAYOUT)
+/// u32 seq
+/// u32 tick
+/// u8  flags            bit 0 = game_over, bit 1 = victory
+/// u8  phase            0 waiting, 1 countdown, 2 playing, 3 paused, 4 finished
+/// f32 countdown
+/// u32 score
+/// u32 marbles_reached_end
+/// f32 elapsed_time
+/// f32 chain_speed
+/// u8  campaign stage   index from 0
+/// u8  campaign stages  0 = not a campaign room
+/// u16 player count, then per player:
+///     u32 id, i16 x, i16 y, i16 z, i16 yaw, u8 loaded color, u8 next color
+/// u16 marble count, then per marble:
+///     u32 id, i16 x, i16 y, i16 z, u8 color
+/// u16 chain count, then per chain marble:
+///     u32 id, u8 track, u16 s, u8 color, u8 flags (bit 0 = frozen, bits 1-3 = power-up:
+///     0 none, 1 slow_down, 2 reverse, 3 bomb, 4 color_blast; bit 4 = hidden)
+/// ```
+///
+/// Colors are `MarbleColor` ids (index into `MarbleColor::ALL`). Static tuning
+/// values from `difficulty` and per-marble velocity/life are omitted.
+pub fn encode_binary(snapshot: &StateSnapshot) -> Vec<u8> {
+    let mut out = Vec::with_capacity(
+        40 + snapshot.players.len() * 16 + snapshot.marbles.len() * 11 + snapshot.chain.len() * 9,
+    );
+
+    out.push(BINARY_STATE);
+    out.push(BINARY_LAYOUT);
+    out.extend_from_slice(&(snapshot.seq as u32).to_le_bytes());
+    out.extend_from_slice(&(snapshot.tick as u32).to_le_bytes());
+    push_scalars(
+        &mut out,
+        scalar_flags(snapshot.game_over, snapshot.outcome),
+        snapshot.phase,
+        snapshot.countdown,
+        snapshot.score,
+        snapshot.marbles_reached_end,
+        &snapshot.difficulty,
+    );
+    push_campaign(&mut out, snapshot.campaign.as_ref());
+    push_players(&mut out, &snapshot.players);
+    push_marbles(&mut out, &snapshot.marbles);
+    push_chain(&mut out, &snapshot.chain);
+    out
+}
+
+/// Encode a delta into the binary layout sent as `Message::Binary`.
+///
+/// ```text
+/// u8  kind (BINARY_DELTA)
+/// u8  layout (BINARY_LAYOUT)
+/// u32 seq
+/// u32 base_seq
+/// u32 tick
+/// scalars as in `encode_binary` (flags through campaign stages)
+/// u16 changed player count, then player records as in `encode_binary`
+/// u16 removed player count, then u32 ids
+/// u16 added marble count, then marble records as in `encode_binary`
+/// u16 changed marble count, then per marble:
+///     u32 id, u8 mask (bit 0 = x, bit 1 = y, bit 2 = z), then an i16 per set bit
+/// u16 removed marble count, then u32 ids
+/// u16 added chain count, then chain records as in `encode_binary`
+/// u16 changed chain count, then per chain marble:
+///     u32 id, u8 mask (bit 0 = s, bit 1 = frozen, bit 2 = hidden), then u16 s,
+///     u8 frozen and u8 hidden for each set bit
+/// u16 removed chain count, then u32 ids
+/// ```
+///
+/// Changes to velocity or life alone are not encoded.
+pub fn encode_binary_delta(delta: &DeltaSnapshot) -> Vec<u8> {
+    let mut out = Vec::with_capacity(
+        50 + delta.players_changed.len() * 16
+            + delta.marbles_added.len() * 11
+            + delta.marbles_changed.len() * 11,
+    );
+
+    out.push(BINARY_DELTA);
+    out.push(BINARY_LAYOUT);
+    out.extend_from_slice(&(delta.seq as u32).to_le_bytes());
+    out.extend_from_slice(&(delta.base_seq as u32).to_le_bytes());
+    out.extend_from_slice(&(delta.tick as u32).to_le_bytes());
+    push_scalars(
+        &mut out,
+        scalar_flags(delta.game_over, delta.outcome),
+        delta.phase,
+        delta.countdown,
+        delta.score,
+        delta.marbles_reached_end,
+        &delta.difficulty,
+    );
+    push_campaign(&mut out, delta.campaign.as_ref());
+    push_players(&mut out, &delta.players_changed);
+    push_ids(&mut out, &delta.players_removed);
+    push_marbles(&mut out, &delta.marbles_added);
+
+    let moved: Vec<(&MarbleDelta, u8)> = delta
+        .marbles_changed
+        .iter()
+        .map(|d| {
+            let mask =
+                (d.x.is_some() as u8) | (d.y.is_some() as u8) << 1 | (d.z.is_some() as u8) << 2;
+            (d, mask)
+        })
+        .filter(|(_, mask)| *mask != 0)
+        .take(u16::MAX as usize)
+        .collect();
+    out.extend_from_slice(&(moved.len() as u16).to_le_bytes());
+    for (d, mask) in moved {
+        out.extend_from_slice(&(d.id as u32).to_le_bytes());
+        out.push(mask);
+        for v in [d.x, d.y, d.z].into_iter().flatten() {
+            out.extend_from_slice(&quantize(v, POSITION_SCALE).to_le_bytes());
+        }
+    }
+
+    push_ids(&mut out, &delta.marbles_removed);
+
+    push_chain(&mut out, &delta.chain_added);
+    push_count(&mut out, delta.chain_changed.len());
+    for d in delta.chain_changed.iter().take(u16::MAX as usize) {
+        out.extend_from_slice(&(d.id as u32).to_le_bytes());
+        out.push(
+            (d.s.is_some() as u8)
+                | (d.frozen.is_some() as u8) << 1
+                | (d.hidden.is_some() as u8) << 2,
+        );
+        if let Some(s) = d.s {
+            out.extend_from_slice(&quantize_s(s).to_le_bytes());
+        }
+        if let Some(frozen) = d.frozen {
+            out.push(frozen as u8);
+        }
+        if let Some(hidden) = d.hidden {
+            out.push(hidden as u8);
+        }
+    }
+    push_ids(&mut out, &delta.chain_removed);
+    out
+}
+
+fn scalar_flags(game_over: bool, outcome: Option<RoundOutcome>) -> u8 {
+    game_over as u8 | ((outcome == Some(RoundOutcome::Victory)) as u8) << 1
+}
+
+fn push_scalars(
+    out: &mut Vec<u8>,
+    flags: u8,
+    phase: GamePhase,
+    countdown: f32,
+    score: u32,
+    marbles_reached_end: u32,
+    difficulty: &Difficulty,
+) {
+    out.push(flags);
+    out.push(phase as u8);
+    out.extend_from_slice(&countdown.to_le_bytes());
+    out.extend_from_slice(&score.to_le_bytes());
+    out.extend_from_slice(&marbles_reached_end.to_le_bytes());
+    out.extend_from_slice(&difficulty.elapsed_time.to_le_bytes());
+    out.extend_from_slice(&difficulty.chain_speed.to_le_bytes());
+}
+
+fn push_campaign(out: &mut Vec<u8>, campaign: Option<&CampaignProgress>) {
+    let (stage, stages) = campaign.map_or((0, 0), |c| (c.stage, c.stages));
+    out.push(stage.min(u8::MAX as usize) as u8);
+    out.push(stages.min(u8::MAX as usize) as u8);
+}
+
+fn push_players(out: &mut Vec<u8>, players: &[Player]) {
+    push_count(out, players.len());
+    for p in players.iter().take(u16::MAX as usize) {
+        out.extend_from_slice(&(p.id as u32).to_le_bytes());
+        push_position(out, p.x, p.y, p.z);
+        out.extend_from_slice(&quantize(p.yaw, YAW_SCALE).to_le_bytes());
+        out.push(color_index(p.loaded_color));
+        out.push(color_index(p.next_color));
+    }
+}
+
+fn push_marbles(out: &mut Vec<u8>, marbles: &[Marble]) {
+    push_count(out, marbles.len());
+    for m in marbles.iter().take(u16::MAX as usize) {
+        out.extend_from_slice(&(m.id as u32).to_le_bytes());
+        push_position(out, m.x, m.y, m.z);
+        out.push(color_index(m.color));
+    }
+}
+
+fn push_chain(out: &mut Vec<u8>, chain: &[ChainEntry]) {
+    push_count(out, chain.len());
+    for c in chain.iter().take(u16::MAX as usize) {
+        out.extend_from_slice(&(c.id as u32).to_le_bytes());
+        out.push(c.track.min(u8::MAX as usize) as u8);
+        out.extend_from_slice(&quantize_s(c.s).to_le_bytes());
+        out.push(color_index(c.color));
+        out.push(c.frozen as u8 | power_code(c.power) << 1 | (c.hidden as u8) << 4);
+    }
+}
+
+fn power_code(power: Option<PowerUp>) -> u8 {
+    match power {
+        None => 0,
+        Some(PowerUp::SlowDown) => 1,
+        Some(PowerUp::Reverse) => 2,
+        Some(PowerUp::Bomb) => 3,
+        Some(PowerUp::ColorBlast) => 4,
+    }
+}
+
+fn push_ids(out: &mut Vec<u8>, ids: &[u64]) {
+    push_count(out, ids.len());
+    for id in ids.iter().take(u16::MAX as usize) {
+        out.extend_from_slice(&(*id as u32).to_le_bytes());
+    }
+}
+
+fn push_count(out: &mut Vec<u8>, len: usize) {
+    out.extend_from_slice(&(len.min(u16::MAX as usize) as u16).to_le_bytes());
+}
+
+fn push_position(out: &mut Vec<u8>, x: f32, y: f32, z: f32) {
+    for v in [x, y, z] {
+        out.extend_from_slice(&quantize(v, POSITION_SCALE).to_le_bytes());
+    }
+}
+
+fn quantize(v: f32, scale: f32) -> i16 {
+    (v * scale).round().clamp(i16::MIN as f32, i16::MAX as f32) as i16
+}
+
+fn quantize_s(s: f32) -> u16 {
+    (s.clamp(0.0, 1.0) * PATH_SCALE).round() as u16
+}
+
+fn color_index(color: MarbleColor) -> u8 {
+    color as u8
+}